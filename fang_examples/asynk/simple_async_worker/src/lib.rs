@@ -4,6 +4,7 @@ use fang::serde::{Deserialize, Serialize};
 use fang::typetag;
 use fang::AsyncRunnable;
 use fang::FangError;
+use fang::TaskContext;
 use std::time::Duration;
 
 #[derive(Serialize, Deserialize)]
@@ -33,7 +34,7 @@ impl MyFailingTask {
 #[async_trait]
 #[typetag::serde]
 impl AsyncRunnable for MyTask {
-    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+    async fn run(&self, queue: &mut dyn AsyncQueueable, _context: &TaskContext) -> Result<(), FangError> {
         let new_task = MyTask::new(self.number + 1);
         queue
             .insert_task(&new_task as &dyn AsyncRunnable)
@@ -50,7 +51,7 @@ impl AsyncRunnable for MyTask {
 #[async_trait]
 #[typetag::serde]
 impl AsyncRunnable for MyFailingTask {
-    async fn run(&self, queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+    async fn run(&self, queue: &mut dyn AsyncQueueable, _context: &TaskContext) -> Result<(), FangError> {
         let new_task = MyFailingTask::new(self.number + 1);
         queue
             .insert_task(&new_task as &dyn AsyncRunnable)
@@ -28,7 +28,7 @@ async fn main() {
 
     log::info!("Pool created ...");
 
-    pool.start().await;
+    let handle = pool.start().await;
     log::info!("Workers started ...");
 
     let task1 = MyTask::new(0);
@@ -51,4 +51,7 @@ async fn main() {
         .unwrap();
 
     tokio::time::sleep(Duration::from_secs(100)).await;
+
+    handle.shutdown().await;
+    log::info!("Workers stopped ...");
 }
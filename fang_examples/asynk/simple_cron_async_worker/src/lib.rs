@@ -4,6 +4,7 @@ use fang::serde::{Deserialize, Serialize};
 use fang::typetag;
 use fang::AsyncRunnable;
 use fang::FangError;
+use fang::TaskContext;
 use fang::Scheduled;
 
 #[derive(Serialize, Deserialize)]
@@ -13,7 +14,7 @@ pub struct MyCronTask {}
 #[async_trait]
 #[typetag::serde]
 impl AsyncRunnable for MyCronTask {
-    async fn run(&self, _queue: &mut dyn AsyncQueueable) -> Result<(), FangError> {
+    async fn run(&self, _queue: &mut dyn AsyncQueueable, _context: &TaskContext) -> Result<(), FangError> {
         log::info!("CRON!!!!!!!!!!!!!!!",);
 
         Ok(())
@@ -2,6 +2,7 @@ use fang::runnable::Runnable;
 use fang::serde::{Deserialize, Serialize};
 use fang::typetag;
 use fang::FangError;
+use fang::TaskContext;
 use fang::Queueable;
 use fang::Scheduled;
 
@@ -11,7 +12,7 @@ pub struct MyCronTask {}
 
 #[typetag::serde]
 impl Runnable for MyCronTask {
-    fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+    fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
         log::info!("CRON !!!!!!!!!!!!!!!!!");
 
         Ok(())
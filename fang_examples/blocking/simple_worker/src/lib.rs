@@ -2,6 +2,7 @@ use fang::runnable::Runnable;
 use fang::serde::{Deserialize, Serialize};
 use fang::typetag;
 use fang::FangError;
+use fang::TaskContext;
 use fang::Queueable;
 use std::thread;
 use std::time::Duration;
@@ -27,7 +28,7 @@ impl MyTask {
 
 #[typetag::serde]
 impl Runnable for MyTask {
-    fn run(&self, queue: &dyn Queueable) -> Result<(), FangError> {
+    fn run(&self, queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
         let new_task = MyTask::new(self.number + 1);
 
         log::info!(
@@ -68,7 +69,7 @@ impl MyFailingTask {
 
 #[typetag::serde]
 impl Runnable for MyFailingTask {
-    fn run(&self, queue: &dyn Queueable) -> Result<(), FangError> {
+    fn run(&self, queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
         let new_task = MyFailingTask::new(self.number + 1);
 
         queue.insert_task(&new_task).unwrap();
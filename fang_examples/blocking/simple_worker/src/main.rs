@@ -44,7 +44,10 @@ fn main() {
         .insert_task(&MyFailingTask::new(5000))
         .unwrap();
 
-    worker_pool.start().unwrap();
+    let handle = worker_pool.start().unwrap();
 
-    sleep(Duration::from_secs(100))
+    sleep(Duration::from_secs(100));
+
+    handle.shutdown();
+    handle.join();
 }
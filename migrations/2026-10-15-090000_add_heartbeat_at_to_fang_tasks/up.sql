@@ -0,0 +1 @@
+ALTER TABLE fang_tasks ADD COLUMN heartbeat_at TIMESTAMP WITH TIME ZONE;
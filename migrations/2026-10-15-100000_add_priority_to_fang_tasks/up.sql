@@ -0,0 +1 @@
+ALTER TABLE fang_tasks ADD COLUMN priority SMALLINT DEFAULT 0 NOT NULL;
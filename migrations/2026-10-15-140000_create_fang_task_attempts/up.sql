@@ -0,0 +1,13 @@
+CREATE TABLE fang_task_attempts (
+     id uuid PRIMARY KEY DEFAULT uuid_generate_v4(),
+     task_id uuid NOT NULL REFERENCES fang_tasks(id) ON DELETE CASCADE,
+     attempt INTEGER NOT NULL,
+     worker_id VARCHAR NOT NULL,
+     outcome fang_task_state NOT NULL,
+     error_message TEXT,
+     error_details jsonb,
+     started_at TIMESTAMP WITH TIME ZONE NOT NULL,
+     finished_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX fang_task_attempts_task_id_index ON fang_task_attempts(task_id);
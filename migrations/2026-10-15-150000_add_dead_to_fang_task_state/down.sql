@@ -0,0 +1,13 @@
+UPDATE fang_tasks SET state = 'failed' WHERE state = 'dead';
+UPDATE fang_task_attempts SET outcome = 'failed' WHERE outcome = 'dead';
+
+ALTER TYPE fang_task_state RENAME TO fang_task_state_old;
+
+CREATE TYPE fang_task_state AS ENUM ('new', 'in_progress', 'failed', 'finished', 'retried');
+
+ALTER TABLE fang_tasks ALTER COLUMN state DROP DEFAULT;
+ALTER TABLE fang_tasks ALTER COLUMN state TYPE fang_task_state USING state::text::fang_task_state;
+ALTER TABLE fang_tasks ALTER COLUMN state SET DEFAULT 'new';
+ALTER TABLE fang_task_attempts ALTER COLUMN outcome TYPE fang_task_state USING outcome::text::fang_task_state;
+
+DROP TYPE fang_task_state_old;
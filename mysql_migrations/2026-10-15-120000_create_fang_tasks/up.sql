@@ -0,0 +1,19 @@
+CREATE TABLE fang_tasks (
+     id CHAR(36) PRIMARY KEY NOT NULL,
+     metadata JSON NOT NULL,
+     error_message TEXT,
+     state VARCHAR(16) DEFAULT 'new' NOT NULL CONSTRAINT fang_tasks_state_check CHECK (state IN ('new', 'in_progress', 'failed', 'finished', 'retried')),
+     task_type VARCHAR(255) DEFAULT 'common' NOT NULL,
+     uniq_hash CHAR(64),
+     retries INTEGER DEFAULT 0 NOT NULL,
+     scheduled_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+     created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+     updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+     heartbeat_at DATETIME(6),
+     priority SMALLINT DEFAULT 0 NOT NULL
+);
+
+CREATE INDEX fang_tasks_state_index ON fang_tasks(state);
+CREATE INDEX fang_tasks_type_index ON fang_tasks(task_type);
+CREATE INDEX fang_tasks_scheduled_at_index ON fang_tasks(scheduled_at);
+CREATE INDEX fang_tasks_uniq_hash ON fang_tasks(uniq_hash);
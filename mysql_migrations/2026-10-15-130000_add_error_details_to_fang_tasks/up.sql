@@ -0,0 +1 @@
+ALTER TABLE fang_tasks ADD COLUMN error_details JSON;
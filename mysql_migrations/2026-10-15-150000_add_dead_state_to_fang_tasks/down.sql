@@ -0,0 +1,3 @@
+UPDATE fang_tasks SET state = 'failed' WHERE state = 'dead';
+ALTER TABLE fang_tasks DROP CONSTRAINT fang_tasks_state_check;
+ALTER TABLE fang_tasks ADD CONSTRAINT fang_tasks_state_check CHECK (state IN ('new', 'in_progress', 'failed', 'finished', 'retried'));
@@ -0,0 +1,19 @@
+CREATE TABLE fang_tasks (
+     id TEXT PRIMARY KEY NOT NULL,
+     metadata TEXT NOT NULL,
+     error_message TEXT,
+     state TEXT CHECK (state IN ('new', 'in_progress', 'failed', 'finished', 'retried')) DEFAULT 'new' NOT NULL,
+     task_type VARCHAR DEFAULT 'common' NOT NULL,
+     uniq_hash CHAR(64),
+     retries INTEGER DEFAULT 0 NOT NULL,
+     scheduled_at TEXT NOT NULL,
+     created_at TEXT NOT NULL,
+     updated_at TEXT NOT NULL,
+     heartbeat_at TEXT,
+     priority SMALLINT DEFAULT 0 NOT NULL
+);
+
+CREATE INDEX fang_tasks_state_index ON fang_tasks(state);
+CREATE INDEX fang_tasks_type_index ON fang_tasks(task_type);
+CREATE INDEX fang_tasks_scheduled_at_index ON fang_tasks(scheduled_at);
+CREATE INDEX fang_tasks_uniq_hash ON fang_tasks(uniq_hash);
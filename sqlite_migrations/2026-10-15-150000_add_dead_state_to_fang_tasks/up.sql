@@ -0,0 +1,28 @@
+-- SQLite can not alter a check constraint, the table is rebuilt
+CREATE TABLE fang_tasks_new (
+     id TEXT PRIMARY KEY NOT NULL,
+     metadata TEXT NOT NULL,
+     error_message TEXT,
+     state TEXT CHECK (state IN ('new', 'in_progress', 'failed', 'finished', 'retried', 'dead')) DEFAULT 'new' NOT NULL,
+     task_type VARCHAR DEFAULT 'common' NOT NULL,
+     uniq_hash CHAR(64),
+     retries INTEGER DEFAULT 0 NOT NULL,
+     scheduled_at TEXT NOT NULL,
+     created_at TEXT NOT NULL,
+     updated_at TEXT NOT NULL,
+     heartbeat_at TEXT,
+     priority SMALLINT DEFAULT 0 NOT NULL,
+     error_details TEXT
+);
+
+INSERT INTO fang_tasks_new (id, metadata, error_message, state, task_type, uniq_hash, retries, scheduled_at, created_at, updated_at, heartbeat_at, priority, error_details)
+SELECT id, metadata, error_message, state, task_type, uniq_hash, retries, scheduled_at, created_at, updated_at, heartbeat_at, priority, error_details FROM fang_tasks;
+
+DROP TABLE fang_tasks;
+
+ALTER TABLE fang_tasks_new RENAME TO fang_tasks;
+
+CREATE INDEX fang_tasks_state_index ON fang_tasks(state);
+CREATE INDEX fang_tasks_type_index ON fang_tasks(task_type);
+CREATE INDEX fang_tasks_scheduled_at_index ON fang_tasks(scheduled_at);
+CREATE INDEX fang_tasks_uniq_hash ON fang_tasks(uniq_hash);
@@ -1,9 +1,16 @@
+pub mod async_in_memory_queue;
+mod async_listener;
 pub mod async_queue;
+#[cfg(feature = "redis")]
+pub mod async_redis_queue;
 pub mod async_runnable;
 pub mod async_worker;
 pub mod async_worker_pool;
 
+pub use async_in_memory_queue::InMemoryAsyncQueue;
 pub use async_queue::*;
+#[cfg(feature = "redis")]
+pub use async_redis_queue::RedisAsyncQueue;
 pub use async_runnable::AsyncRunnable;
 pub use async_worker::*;
 pub use async_worker_pool::*;
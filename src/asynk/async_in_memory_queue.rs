@@ -0,0 +1,1012 @@
+use crate::asynk::async_queue::AsyncQueue;
+use crate::asynk::async_queue::AsyncQueueError;
+use crate::asynk::async_queue::AsyncQueueable;
+use crate::asynk::async_queue::FangTaskState;
+use crate::asynk::async_queue::Task;
+use crate::asynk::async_queue::TaskAttempt;
+use crate::asynk::async_queue::DEFAULT_TASK_TYPE;
+use crate::asynk::async_runnable::AsyncRunnable;
+use crate::in_memory_store::InMemoryStore;
+use crate::new_tasks::NewTasks;
+use crate::FangError;
+use crate::EXPIRED_HEARTBEAT_ERROR;
+use async_trait::async_trait;
+use bb8_postgres::tokio_postgres::tls::NoTls;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// An async queue that keeps tasks in memory.
+///
+/// It implements [`AsyncQueueable`] with the same semantics as [`AsyncQueue`],
+/// so tasks can be tested and workers can be run without a database.
+/// Clones of the queue share the same tasks, they are lost once the last clone is dropped.
+///
+///    ```rust
+///         let mut queue = InMemoryAsyncQueue::default();
+///
+///         queue.insert_task(&MyTask { number: 1 }).await?;
+///     ```
+///
+/// Idle workers are woken up every time a task is enqueued or retried.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryAsyncQueue {
+    store: InMemoryStore,
+    notify: Arc<Notify>,
+    record_reaped_attempts: bool,
+}
+
+impl InMemoryAsyncQueue {
+    /// Set if `reap_expired_tasks` records the reaped executions of tasks
+    /// as attempts of the `REAPER_WORKER_ID`, it doesn't by default
+    pub fn record_reaped_attempts(mut self, record_reaped_attempts: bool) -> Self {
+        self.record_reaped_attempts = record_reaped_attempts;
+        self
+    }
+
+    /// Return all tasks that are stored in the queue
+    pub fn tasks(&self) -> Vec<Task> {
+        self.store.tasks()
+    }
+
+    fn insert(
+        &self,
+        tasks: &[&dyn AsyncRunnable],
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<Vec<Task>, AsyncQueueError> {
+        let mut new_tasks = NewTasks::default();
+
+        for task in tasks {
+            let metadata = serde_json::to_value(task)?;
+
+            new_tasks.add(
+                metadata,
+                task.task_type(),
+                task.uniq(),
+                task.priority(),
+                scheduled_at,
+            );
+        }
+
+        Ok(self.store.insert(new_tasks))
+    }
+
+    fn update(&self, id: Uuid, update: impl FnOnce(&mut Task)) -> Result<Task, AsyncQueueError> {
+        self.store
+            .update(id, update)
+            .ok_or(AsyncQueueError::ResultError {
+                expected: 1,
+                found: 0,
+            })
+    }
+}
+
+#[async_trait]
+impl AsyncQueueable for InMemoryAsyncQueue {
+    async fn fetch_and_touch_task(
+        &mut self,
+        task_type: Option<String>,
+    ) -> Result<Option<Task>, AsyncQueueError> {
+        let mut tasks = self.fetch_and_touch_tasks(task_type, 1).await?;
+
+        Ok(tasks.pop())
+    }
+
+    async fn fetch_and_touch_tasks(
+        &mut self,
+        task_type: Option<String>,
+        limit: u32,
+    ) -> Result<Vec<Task>, AsyncQueueError> {
+        let task_type = task_type.unwrap_or_else(|| DEFAULT_TASK_TYPE.to_string());
+
+        Ok(self.store.fetch_and_touch(&task_type, limit))
+    }
+
+    async fn insert_task(&mut self, task: &dyn AsyncRunnable) -> Result<Task, AsyncQueueError> {
+        let mut tasks = self.insert(&[task], Utc::now())?;
+
+        self.notify.notify_waiters();
+
+        Ok(tasks.pop().unwrap())
+    }
+
+    async fn insert_tasks(
+        &mut self,
+        tasks: &[&dyn AsyncRunnable],
+    ) -> Result<Vec<Task>, AsyncQueueError> {
+        let inserted_tasks = self.insert(tasks, Utc::now())?;
+
+        self.notify.notify_waiters();
+
+        Ok(inserted_tasks)
+    }
+
+    async fn remove_all_tasks(&mut self) -> Result<u64, AsyncQueueError> {
+        Ok(self.store.remove(|_| true) as u64)
+    }
+
+    async fn remove_all_scheduled_tasks(&mut self) -> Result<u64, AsyncQueueError> {
+        let now = Utc::now();
+
+        Ok(self.store.remove(|task| task.scheduled_at > now) as u64)
+    }
+
+    async fn remove_task(&mut self, id: Uuid) -> Result<u64, AsyncQueueError> {
+        let removed = self.store.remove(|task| task.id == id) as u64;
+
+        if removed != 1 {
+            return Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: removed,
+            });
+        }
+
+        Ok(removed)
+    }
+
+    async fn remove_task_by_metadata(
+        &mut self,
+        task: &dyn AsyncRunnable,
+    ) -> Result<u64, AsyncQueueError> {
+        if task.uniq() {
+            let metadata = serde_json::to_value(task)?;
+            let uniq_hash = AsyncQueue::<NoTls>::calculate_hash(metadata.to_string());
+
+            Ok(self
+                .store
+                .remove(|task| task.uniq_hash.as_ref() == Some(&uniq_hash)) as u64)
+        } else {
+            Err(AsyncQueueError::TaskNotUniqError)
+        }
+    }
+
+    async fn remove_tasks_type(&mut self, task_type: &str) -> Result<u64, AsyncQueueError> {
+        Ok(self.store.remove(|task| task.task_type == task_type) as u64)
+    }
+
+    async fn find_task_by_id(&mut self, id: Uuid) -> Result<Task, AsyncQueueError> {
+        self.store.find(id).ok_or(AsyncQueueError::ResultError {
+            expected: 1,
+            found: 0,
+        })
+    }
+
+    async fn update_task_state(
+        &mut self,
+        task: Task,
+        state: FangTaskState,
+    ) -> Result<Task, AsyncQueueError> {
+        self.update(task.id, |task| {
+            task.state = state;
+            task.updated_at = Utc::now();
+        })
+    }
+
+    async fn fail_task(&mut self, task: Task, error: &FangError) -> Result<Task, AsyncQueueError> {
+        self.update(task.id, |task| {
+            InMemoryStore::fail(
+                task,
+                FangTaskState::Failed,
+                &error.description,
+                error.details.as_ref(),
+            )
+        })
+    }
+
+    async fn dead_letter_task(
+        &mut self,
+        task: Task,
+        error: &FangError,
+    ) -> Result<Task, AsyncQueueError> {
+        self.update(task.id, |task| {
+            InMemoryStore::fail(
+                task,
+                FangTaskState::Dead,
+                &error.description,
+                error.details.as_ref(),
+            )
+        })
+    }
+
+    async fn schedule_task(&mut self, task: &dyn AsyncRunnable) -> Result<Task, AsyncQueueError> {
+        let scheduled_at = AsyncQueue::<NoTls>::calculate_scheduled_at(task)?;
+        let mut tasks = self.insert(&[task], scheduled_at)?;
+
+        Ok(tasks.pop().unwrap())
+    }
+
+    async fn schedule_retry(
+        &mut self,
+        task: &Task,
+        backoff_seconds: u32,
+        error: &FangError,
+    ) -> Result<Task, AsyncQueueError> {
+        let retried_task = self.update(task.id, |stored_task| {
+            InMemoryStore::retry(
+                stored_task,
+                task.retries,
+                backoff_seconds,
+                &error.description,
+                error.details.as_ref(),
+            )
+        })?;
+
+        self.notify.notify_waiters();
+
+        Ok(retried_task)
+    }
+
+    async fn heartbeat_task(&mut self, task: &Task) -> Result<u64, AsyncQueueError> {
+        Ok(self.store.heartbeat(task) as u64)
+    }
+
+    async fn reap_expired_tasks(
+        &mut self,
+        heartbeat_timeout: std::time::Duration,
+    ) -> Result<u64, AsyncQueueError> {
+        let heartbeat_timeout =
+            Duration::from_std(heartbeat_timeout).map_err(|_| AsyncQueueError::TimeError)?;
+        let expired_at = Utc::now() - heartbeat_timeout;
+
+        let reaped = self.store.reap(
+            expired_at,
+            EXPIRED_HEARTBEAT_ERROR,
+            self.record_reaped_attempts,
+            |task| match serde_json::from_value::<Box<dyn AsyncRunnable>>(task.metadata.clone()) {
+                Ok(runnable) if task.retries < runnable.max_retries() => {
+                    Some(Some(runnable.backoff(task.retries as u32)))
+                }
+                Ok(_) => Some(None),
+                Err(_) => None,
+            },
+        );
+
+        if reaped > 0 {
+            self.notify.notify_waiters();
+        }
+
+        Ok(reaped as u64)
+    }
+
+    async fn retry_failed_task(&mut self, id: Uuid) -> Result<Task, AsyncQueueError> {
+        let task = self.store.retry_failed(|task| task.id == id).pop().ok_or(
+            AsyncQueueError::ResultError {
+                expected: 1,
+                found: 0,
+            },
+        )?;
+
+        self.notify.notify_waiters();
+
+        Ok(task)
+    }
+
+    async fn retry_failed_tasks_of_type(
+        &mut self,
+        task_type: &str,
+    ) -> Result<u64, AsyncQueueError> {
+        let retried = self
+            .store
+            .retry_failed(|task| task.task_type == task_type)
+            .len();
+
+        if retried > 0 {
+            self.notify.notify_waiters();
+        }
+
+        Ok(retried as u64)
+    }
+
+    async fn discard_dead_tasks(
+        &mut self,
+        older_than: std::time::Duration,
+    ) -> Result<u64, AsyncQueueError> {
+        let older_than = Duration::from_std(older_than).map_err(|_| AsyncQueueError::TimeError)?;
+        let updated_before = Utc::now() - older_than;
+
+        Ok(self
+            .store
+            .remove(|task| task.state == FangTaskState::Dead && task.updated_at < updated_before)
+            as u64)
+    }
+
+    async fn record_task_attempt(&mut self, attempt: &TaskAttempt) -> Result<(), AsyncQueueError> {
+        if self.store.record_attempt(attempt) {
+            Ok(())
+        } else {
+            Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: 0,
+            })
+        }
+    }
+
+    async fn find_task_attempts(
+        &mut self,
+        task_id: Uuid,
+    ) -> Result<Vec<TaskAttempt>, AsyncQueueError> {
+        Ok(self.store.find_attempts(task_id))
+    }
+
+    async fn wait_for_task(&mut self, _task_type: &str, timeout: std::time::Duration) {
+        let _ = tokio::time::timeout(timeout, self.notify.notified()).await;
+    }
+}
+
+#[cfg(test)]
+mod async_in_memory_queue_tests {
+    use super::InMemoryAsyncQueue;
+    use crate::asynk::async_queue::AsyncQueueError;
+    use crate::asynk::async_queue::AsyncQueueable;
+    use crate::asynk::async_queue::FangTaskState;
+    use crate::asynk::async_queue::TaskAttempt;
+    use crate::asynk::async_worker::AsyncWorker;
+    use crate::asynk::AsyncRunnable;
+    use crate::FangError;
+    use crate::RetentionMode;
+    use crate::Scheduled;
+    use crate::TaskContext;
+    use crate::EXPIRED_HEARTBEAT_ERROR;
+    use async_trait::async_trait;
+    use chrono::DateTime;
+    use chrono::Duration;
+    use chrono::SubsecRound;
+    use chrono::Utc;
+    use serde::{Deserialize, Serialize};
+    use tokio_util::sync::CancellationToken;
+
+    #[derive(Serialize, Deserialize)]
+    struct InMemoryTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for InMemoryTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct InMemoryUrgentTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for InMemoryUrgentTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn priority(&self) -> i16 {
+            10
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct InMemoryUniqTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for InMemoryUniqTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn uniq(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct InMemoryScheduledTask {
+        pub number: u16,
+        pub datetime: String,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for InMemoryScheduledTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn cron(&self) -> Option<Scheduled> {
+            let datetime = self.datetime.parse::<DateTime<Utc>>().ok()?;
+            Some(Scheduled::ScheduleOnce(datetime))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct InMemoryFailingTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for InMemoryFailingTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Err(FangError::retryable("Failed"))
+        }
+
+        fn task_type(&self) -> String {
+            "in_memory_failing".to_string()
+        }
+
+        fn max_retries(&self) -> i32 {
+            1
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct InMemoryDeletedRecordTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for InMemoryDeletedRecordTask {
+        async fn run(
+            &self,
+            queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            // the record of the task was deleted
+            queueable.find_task_by_id(uuid::Uuid::nil()).await?;
+
+            Ok(())
+        }
+
+        fn task_type(&self) -> String {
+            "in_memory_deleted_record".to_string()
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct InMemoryRateLimitedTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for InMemoryRateLimitedTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Err(FangError::retry_after(
+                "Rate limited",
+                std::time::Duration::from_millis(500),
+            ))
+        }
+
+        fn task_type(&self) -> String {
+            "in_memory_rate_limited".to_string()
+        }
+    }
+
+    async fn run_worker(queue: &InMemoryAsyncQueue, task_type: &str) {
+        let shutdown_token = CancellationToken::new();
+
+        let mut worker: AsyncWorker<InMemoryAsyncQueue> = AsyncWorker::builder()
+            .queue(queue.clone())
+            .task_type(task_type)
+            .retention_mode(RetentionMode::KeepAll)
+            .shutdown_token(shutdown_token.clone())
+            .build();
+
+        let join_handle = tokio::spawn(async move { worker.run_tasks().await });
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        shutdown_token.cancel();
+
+        assert!(join_handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn insert_task_creates_new_task() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+
+        assert_eq!(Some(1), task.metadata["number"].as_u64());
+        assert_eq!(Some("InMemoryTask"), task.metadata["type"].as_str());
+        assert_eq!(FangTaskState::New, task.state);
+        assert_eq!(None, task.uniq_hash);
+        assert_eq!(0, task.retries);
+        assert_eq!(vec![task.clone()], queue.tasks());
+        assert_eq!(task, queue.find_task_by_id(task.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn insert_task_does_not_duplicate_uniq_tasks() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task1 = queue
+            .insert_task(&InMemoryUniqTask { number: 1 })
+            .await
+            .unwrap();
+        let task2 = queue
+            .insert_task(&InMemoryUniqTask { number: 1 })
+            .await
+            .unwrap();
+        let tasks = queue
+            .insert_tasks(&[
+                &InMemoryUniqTask { number: 1 },
+                &InMemoryUniqTask { number: 2 },
+                &InMemoryUniqTask { number: 2 },
+            ])
+            .await
+            .unwrap();
+
+        assert!(task1.uniq_hash.is_some());
+        assert_eq!(task1.id, task2.id);
+        assert_eq!(task1.id, tasks[0].id);
+        assert_eq!(tasks[1].id, tasks[2].id);
+        assert_eq!(2, queue.tasks().len());
+
+        queue
+            .update_task_state(task1.clone(), FangTaskState::Finished)
+            .await
+            .unwrap();
+
+        let task3 = queue
+            .insert_task(&InMemoryUniqTask { number: 1 })
+            .await
+            .unwrap();
+
+        assert_ne!(task1.id, task3.id);
+    }
+
+    #[tokio::test]
+    async fn fetch_and_touch_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task1 = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+        let task2 = queue
+            .insert_task(&InMemoryTask { number: 2 })
+            .await
+            .unwrap();
+
+        let fetched_task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        assert_eq!(task1.id, fetched_task.id);
+        assert_eq!(FangTaskState::InProgress, fetched_task.state);
+        assert!(fetched_task.heartbeat_at.is_some());
+
+        let fetched_task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        assert_eq!(task2.id, fetched_task.id);
+        assert_eq!(None, queue.fetch_and_touch_task(None).await.unwrap());
+        assert_eq!(
+            None,
+            queue
+                .fetch_and_touch_task(Some("other_type".to_string()))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_and_touch_tasks_respects_priority() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task1 = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+        let task2 = queue
+            .insert_task(&InMemoryUrgentTask { number: 2 })
+            .await
+            .unwrap();
+        queue
+            .insert_task(&InMemoryTask { number: 3 })
+            .await
+            .unwrap();
+
+        let tasks = queue.fetch_and_touch_tasks(None, 2).await.unwrap();
+
+        assert_eq!(2, tasks.len());
+        assert_eq!(task2.id, tasks[0].id);
+        assert_eq!(task1.id, tasks[1].id);
+
+        let tasks = queue.fetch_and_touch_tasks(None, 2).await.unwrap();
+
+        assert_eq!(1, tasks.len());
+    }
+
+    #[tokio::test]
+    async fn schedule_task_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let datetime = (Utc::now() + Duration::seconds(7)).round_subsecs(0);
+
+        let task = queue
+            .schedule_task(&InMemoryScheduledTask {
+                number: 1,
+                datetime: datetime.to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(datetime, task.scheduled_at);
+        assert_eq!(None, queue.fetch_and_touch_task(None).await.unwrap());
+        assert_eq!(1, queue.remove_all_scheduled_tasks().await.unwrap());
+        assert!(queue.tasks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn schedule_retry_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+
+        let task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        let retried_task = queue
+            .schedule_retry(&task, 60, &FangError::retryable("Failed"))
+            .await
+            .unwrap();
+
+        assert_eq!(FangTaskState::Retried, retried_task.state);
+        assert_eq!(1, retried_task.retries);
+        assert_eq!(Some("Failed".to_string()), retried_task.error_message);
+        assert!(retried_task.scheduled_at > Utc::now() + Duration::seconds(50));
+        assert_eq!(None, queue.fetch_and_touch_task(None).await.unwrap());
+
+        let retried_task = queue
+            .schedule_retry(&retried_task, 0, &FangError::retryable("Failed"))
+            .await
+            .unwrap();
+
+        assert_eq!(2, retried_task.retries);
+
+        let fetched_task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        assert_eq!(retried_task.id, fetched_task.id);
+    }
+
+    #[tokio::test]
+    async fn fail_task_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+
+        let failed_task = queue
+            .fail_task(task, &FangError::retryable("Some error"))
+            .await
+            .unwrap();
+
+        assert_eq!(FangTaskState::Failed, failed_task.state);
+        assert_eq!(Some("Some error".to_string()), failed_task.error_message);
+        assert_eq!(None, queue.fetch_and_touch_task(None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn retry_failed_tasks_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task1 = queue
+            .insert_task(&InMemoryFailingTask { number: 1 })
+            .await
+            .unwrap();
+        let task2 = queue
+            .insert_task(&InMemoryFailingTask { number: 2 })
+            .await
+            .unwrap();
+
+        let error = FangError::permanent("Failed");
+        queue.fail_task(task1.clone(), &error).await.unwrap();
+        queue.dead_letter_task(task2.clone(), &error).await.unwrap();
+
+        let retried_task = queue.retry_failed_task(task1.id).await.unwrap();
+
+        assert_eq!(FangTaskState::New, retried_task.state);
+        assert_eq!(0, retried_task.retries);
+        assert!(queue.retry_failed_task(task1.id).await.is_err());
+
+        assert_eq!(
+            1,
+            queue
+                .retry_failed_tasks_of_type("in_memory_failing")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            FangTaskState::New,
+            queue.find_task_by_id(task2.id).await.unwrap().state
+        );
+    }
+
+    #[tokio::test]
+    async fn task_attempts_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+        let started_at = Utc::now();
+
+        let second_attempt = TaskAttempt::builder()
+            .id(uuid::Uuid::new_v4())
+            .task_id(task.id)
+            .attempt(2)
+            .worker_id("worker_common0")
+            .outcome(FangTaskState::Finished)
+            .started_at(started_at + Duration::seconds(10))
+            .finished_at(started_at + Duration::seconds(11))
+            .build();
+        let first_attempt = TaskAttempt::builder()
+            .id(uuid::Uuid::new_v4())
+            .task_id(task.id)
+            .attempt(1)
+            .worker_id("worker_common0")
+            .outcome(FangTaskState::Retried)
+            .error_message(Some("Failed".to_string()))
+            .started_at(started_at)
+            .finished_at(started_at + Duration::seconds(1))
+            .build();
+
+        queue.record_task_attempt(&second_attempt).await.unwrap();
+        queue.record_task_attempt(&first_attempt).await.unwrap();
+
+        let attempts = queue.find_task_attempts(task.id).await.unwrap();
+
+        assert_eq!(vec![first_attempt.clone(), second_attempt], attempts);
+
+        queue.remove_task(task.id).await.unwrap();
+
+        assert!(queue.find_task_attempts(task.id).await.unwrap().is_empty());
+        assert!(queue.record_task_attempt(&first_attempt).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn discard_dead_tasks_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task1 = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+        let task2 = queue
+            .insert_task(&InMemoryTask { number: 2 })
+            .await
+            .unwrap();
+
+        queue
+            .dead_letter_task(task1.clone(), &FangError::permanent("Failed"))
+            .await
+            .unwrap();
+        queue
+            .fail_task(task2.clone(), &FangError::permanent("Failed"))
+            .await
+            .unwrap();
+
+        let older_than = std::time::Duration::from_secs(60);
+        assert_eq!(0, queue.discard_dead_tasks(older_than).await.unwrap());
+
+        let older_than = std::time::Duration::ZERO;
+        assert_eq!(1, queue.discard_dead_tasks(older_than).await.unwrap());
+
+        assert!(queue.find_task_by_id(task1.id).await.is_err());
+        assert!(queue.find_task_by_id(task2.id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn remove_tasks_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+        queue
+            .insert_task(&InMemoryUniqTask { number: 1 })
+            .await
+            .unwrap();
+        queue
+            .insert_task(&InMemoryFailingTask { number: 1 })
+            .await
+            .unwrap();
+
+        assert_eq!(1, queue.remove_task(task.id).await.unwrap());
+        assert!(matches!(
+            queue.remove_task(task.id).await,
+            Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: 0
+            })
+        ));
+        assert!(matches!(
+            queue
+                .remove_task_by_metadata(&InMemoryTask { number: 1 })
+                .await,
+            Err(AsyncQueueError::TaskNotUniqError)
+        ));
+        assert_eq!(
+            1,
+            queue
+                .remove_task_by_metadata(&InMemoryUniqTask { number: 1 })
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            1,
+            queue.remove_tasks_type("in_memory_failing").await.unwrap()
+        );
+        assert!(queue.tasks().is_empty());
+        assert!(queue.find_task_by_id(task.id).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reap_expired_tasks_test() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+
+        let task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        assert_eq!(1, queue.heartbeat_task(&task).await.unwrap());
+        assert_eq!(
+            0,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        assert_eq!(
+            1,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_millis(1))
+                .await
+                .unwrap()
+        );
+
+        let reaped_task = queue.find_task_by_id(task.id).await.unwrap();
+
+        assert_eq!(FangTaskState::Retried, reaped_task.state);
+        assert_eq!(1, reaped_task.retries);
+        assert_eq!(
+            Some(EXPIRED_HEARTBEAT_ERROR.to_string()),
+            reaped_task.error_message
+        );
+        assert_eq!(0, queue.heartbeat_task(&reaped_task).await.unwrap());
+        assert!(queue.find_task_attempts(task.id).await.unwrap().is_empty());
+
+        // the task is executed again, the heartbeats of its previous execution are ignored
+        let restarted_task = queue
+            .update_task_state(reaped_task, FangTaskState::InProgress)
+            .await
+            .unwrap();
+
+        assert_eq!(0, queue.heartbeat_task(&task).await.unwrap());
+        assert_eq!(1, queue.heartbeat_task(&restarted_task).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn worker_executes_tasks() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task = queue
+            .insert_task(&InMemoryTask { number: 1 })
+            .await
+            .unwrap();
+        let failing_task = queue
+            .insert_task(&InMemoryFailingTask { number: 1 })
+            .await
+            .unwrap();
+
+        let shutdown_token = CancellationToken::new();
+
+        let mut worker: AsyncWorker<InMemoryAsyncQueue> = AsyncWorker::builder()
+            .queue(queue.clone())
+            .retention_mode(RetentionMode::KeepAll)
+            .shutdown_token(shutdown_token.clone())
+            .build();
+
+        let mut failing_worker: AsyncWorker<InMemoryAsyncQueue> = AsyncWorker::builder()
+            .queue(queue.clone())
+            .task_type("in_memory_failing")
+            .retention_mode(RetentionMode::KeepAll)
+            .shutdown_token(shutdown_token.clone())
+            .build();
+
+        let join_handle = tokio::spawn(async move { worker.run_tasks().await });
+        let failing_join_handle = tokio::spawn(async move { failing_worker.run_tasks().await });
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        shutdown_token.cancel();
+
+        assert!(join_handle.await.unwrap().is_ok());
+        assert!(failing_join_handle.await.unwrap().is_ok());
+
+        let task = queue.find_task_by_id(task.id).await.unwrap();
+        let failing_task = queue.find_task_by_id(failing_task.id).await.unwrap();
+
+        assert_eq!(FangTaskState::Finished, task.state);
+        assert_eq!(FangTaskState::Retried, failing_task.state);
+        assert_eq!(1, failing_task.retries);
+        assert_eq!(Some("Failed".to_string()), failing_task.error_message);
+    }
+
+    #[tokio::test]
+    async fn worker_does_not_retry_tasks_whose_records_were_deleted() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task = queue
+            .insert_task(&InMemoryDeletedRecordTask { number: 1 })
+            .await
+            .unwrap();
+
+        run_worker(&queue, "in_memory_deleted_record").await;
+
+        let task = queue.find_task_by_id(task.id).await.unwrap();
+
+        assert_eq!(FangTaskState::Failed, task.state);
+        assert_eq!(0, task.retries);
+    }
+
+    #[tokio::test]
+    async fn worker_rounds_up_retry_after_shorter_than_second() {
+        let mut queue = InMemoryAsyncQueue::default();
+
+        let task = queue
+            .insert_task(&InMemoryRateLimitedTask { number: 1 })
+            .await
+            .unwrap();
+
+        run_worker(&queue, "in_memory_rate_limited").await;
+
+        let task = queue.find_task_by_id(task.id).await.unwrap();
+
+        assert_eq!(FangTaskState::Retried, task.state);
+        assert_eq!(Duration::seconds(1), task.scheduled_at - task.updated_at);
+    }
+}
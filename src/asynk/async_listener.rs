@@ -0,0 +1,212 @@
+use crate::asynk::async_queue::AsyncQueueError;
+use crate::listen_query;
+use crate::notification_channel;
+use bb8_postgres::tokio_postgres;
+use bb8_postgres::tokio_postgres::tls::{MakeTlsConnect, TlsConnect};
+use bb8_postgres::tokio_postgres::AsyncMessage;
+use bb8_postgres::tokio_postgres::Client;
+use bb8_postgres::tokio_postgres::Connection;
+use bb8_postgres::tokio_postgres::Socket;
+use futures_util::stream;
+use futures_util::StreamExt;
+use log::error;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// How long the listener waits before it reconnects after its connection failed
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Listens for notifications about new tasks on a dedicated connection
+/// and wakes up workers that are waiting for tasks of the notified type.
+///
+/// If the connection fails, the listener reconnects and listens again on the channels of all waiting workers.
+#[derive(Debug)]
+pub(crate) struct TaskListener {
+    subscriptions: Arc<Subscriptions>,
+    schema: Option<String>,
+}
+
+#[derive(Debug)]
+struct Subscriptions {
+    /// `None` while the listener is reconnecting
+    client: tokio::sync::Mutex<Option<Client>>,
+    notifiers: Mutex<HashMap<String, Arc<Notify>>>,
+}
+
+impl TaskListener {
+    pub(crate) async fn connect<Tls>(
+        uri: &str,
+        tls: Tls,
+        schema: Option<String>,
+    ) -> Result<Self, AsyncQueueError>
+    where
+        Tls: MakeTlsConnect<Socket> + Clone + Send + Sync + 'static,
+        <Tls as MakeTlsConnect<Socket>>::Stream: Send + Sync,
+        <Tls as MakeTlsConnect<Socket>>::TlsConnect: Send,
+        <<Tls as MakeTlsConnect<Socket>>::TlsConnect as TlsConnect<Socket>>::Future: Send,
+    {
+        let (client, connection) = tokio_postgres::connect(uri, tls.clone()).await?;
+
+        let subscriptions = Arc::new(Subscriptions {
+            client: tokio::sync::Mutex::new(Some(client)),
+            notifiers: Mutex::default(),
+        });
+
+        tokio::spawn(Self::receive_notifications(
+            uri.to_string(),
+            tls,
+            connection,
+            Arc::downgrade(&subscriptions),
+        ));
+
+        Ok(TaskListener {
+            subscriptions,
+            schema,
+        })
+    }
+
+    /// Wakes up workers when their tasks are notified and reconnects when the connection fails.
+    /// It stops once the listener is dropped
+    async fn receive_notifications<Tls>(
+        uri: String,
+        tls: Tls,
+        mut connection: Connection<Socket, <Tls as MakeTlsConnect<Socket>>::Stream>,
+        subscriptions: Weak<Subscriptions>,
+    ) where
+        Tls: MakeTlsConnect<Socket> + Clone + Send + Sync + 'static,
+        <Tls as MakeTlsConnect<Socket>>::Stream: Send + Sync,
+        <Tls as MakeTlsConnect<Socket>>::TlsConnect: Send,
+        <<Tls as MakeTlsConnect<Socket>>::TlsConnect as TlsConnect<Socket>>::Future: Send,
+    {
+        loop {
+            let mut messages = stream::poll_fn(|cx| connection.poll_message(cx));
+
+            while let Some(message) = messages.next().await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        let subscriptions = match subscriptions.upgrade() {
+                            Some(subscriptions) => subscriptions,
+                            None => return,
+                        };
+
+                        let notifiers = subscriptions.notifiers.lock().unwrap();
+
+                        if let Some(notify) = notifiers.get(notification.channel()) {
+                            notify.notify_waiters();
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        error!("The task notifications connection failed {:?}", error);
+                        break;
+                    }
+                }
+            }
+
+            // the client is dropped together with the listener, which closes the connection
+            let failed_subscriptions = match subscriptions.upgrade() {
+                Some(subscriptions) => subscriptions,
+                None => return,
+            };
+            *failed_subscriptions.client.lock().await = None;
+            drop(failed_subscriptions);
+
+            connection = loop {
+                tokio::time::sleep(RECONNECT_INTERVAL).await;
+
+                let subscriptions = match subscriptions.upgrade() {
+                    Some(subscriptions) => subscriptions,
+                    None => return,
+                };
+
+                match tokio_postgres::connect(&uri, tls.clone()).await {
+                    Ok((client, connection)) => {
+                        // the connection has to be polled for the client to execute `LISTEN`
+                        tokio::spawn(Self::resubscribe(subscriptions, client));
+                        break connection;
+                    }
+                    Err(error) => {
+                        error!(
+                            "Failed to reconnect the task notifications connection {:?}",
+                            error
+                        );
+                    }
+                }
+            };
+        }
+    }
+
+    /// Listen again on the channels of all waiting workers with a new `client`
+    async fn resubscribe(subscriptions: Arc<Subscriptions>, client: Client) {
+        let mut current_client = subscriptions.client.lock().await;
+
+        let channels: Vec<String> = subscriptions
+            .notifiers
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+
+        for channel in channels {
+            if let Err(error) = client.batch_execute(&listen_query(&channel)).await {
+                error!("Failed to listen on the channel {} {:?}", channel, error);
+            }
+        }
+
+        *current_client = Some(client);
+
+        // tasks that were enqueued while the listener was disconnected weren't notified
+        for notify in subscriptions.notifiers.lock().unwrap().values() {
+            notify.notify_waiters();
+        }
+    }
+
+    /// Start listening for notifications about tasks of `task_type`
+    pub(crate) async fn subscribe(&self, task_type: &str) -> Result<Arc<Notify>, AsyncQueueError> {
+        let channel = notification_channel(self.schema.as_deref(), task_type);
+
+        let (notify, subscribed) = {
+            let mut notifiers = self.subscriptions.notifiers.lock().unwrap();
+
+            match notifiers.get(&channel) {
+                Some(notify) => (notify.clone(), true),
+                None => (notifiers.entry(channel.clone()).or_default().clone(), false),
+            }
+        };
+
+        if !subscribed {
+            // while reconnecting, the channel is listened on when the connection is established
+            if let Some(client) = &*self.subscriptions.client.lock().await {
+                if let Err(error) = client.batch_execute(&listen_query(&channel)).await {
+                    self.subscriptions
+                        .notifiers
+                        .lock()
+                        .unwrap()
+                        .remove(&channel);
+
+                    return Err(error.into());
+                }
+            }
+        }
+
+        Ok(notify)
+    }
+
+    /// Wait until a task of `task_type` is enqueued or the `timeout` expires
+    pub(crate) async fn wait_for_task(
+        &self,
+        task_type: &str,
+        timeout: Duration,
+    ) -> Result<(), AsyncQueueError> {
+        let notify = self.subscribe(task_type).await?;
+
+        let _ = tokio::time::timeout(timeout, notify.notified()).await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1406 @@
+use crate::asynk::async_queue::AsyncQueue;
+use crate::asynk::async_queue::AsyncQueueError;
+use crate::asynk::async_queue::AsyncQueueable;
+use crate::asynk::async_queue::FangTaskState;
+use crate::asynk::async_queue::Task;
+use crate::asynk::async_queue::DEFAULT_TASK_TYPE;
+use crate::asynk::async_runnable::AsyncRunnable;
+use crate::FangError;
+use crate::EXPIRED_HEARTBEAT_ERROR;
+use async_trait::async_trait;
+use bb8_postgres::tokio_postgres::tls::NoTls;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use redis::ErrorKind;
+use redis::RedisError;
+use redis::Script;
+use redis::ScriptInvocation;
+use std::collections::HashMap;
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+const DEFAULT_PREFIX: &str = "fang";
+
+/// The maximum number of tasks that are removed by one script, so Redis isn't blocked for long
+const REMOVE_BATCH_SIZE: usize = 100;
+
+const COMMON_SCRIPT: &str = include_str!("redis_scripts/common.lua");
+const INSERT_TASK_SCRIPT: &str = include_str!("redis_scripts/insert_task.lua");
+const FETCH_AND_TOUCH_TASKS_SCRIPT: &str = include_str!("redis_scripts/fetch_and_touch_tasks.lua");
+const UPDATE_TASK_SCRIPT: &str = include_str!("redis_scripts/update_task.lua");
+const REMOVE_TASKS_SCRIPT: &str = include_str!("redis_scripts/remove_tasks.lua");
+
+/// Lua scripts that change tasks and their indexes atomically
+struct RedisScripts {
+    insert_task: Script,
+    fetch_and_touch_tasks: Script,
+    update_task: Script,
+    remove_tasks: Script,
+}
+
+impl Default for RedisScripts {
+    fn default() -> Self {
+        let script = |body: &str| Script::new(&format!("{}{}", COMMON_SCRIPT, body));
+
+        Self {
+            insert_task: script(INSERT_TASK_SCRIPT),
+            fetch_and_touch_tasks: script(FETCH_AND_TOUCH_TASKS_SCRIPT),
+            update_task: script(UPDATE_TASK_SCRIPT),
+            remove_tasks: script(REMOVE_TASKS_SCRIPT),
+        }
+    }
+}
+
+/// The fields of a task that its keys are derived from, they don't change after the task is inserted
+struct IndexedTask {
+    id: String,
+    task_type: String,
+    priority: String,
+    uniq_hash: Option<String>,
+}
+
+impl From<&Task> for IndexedTask {
+    fn from(task: &Task) -> Self {
+        Self {
+            id: task.id.to_string(),
+            task_type: task.task_type.clone(),
+            priority: task.priority.to_string(),
+            uniq_hash: task.uniq_hash.clone(),
+        }
+    }
+}
+
+/// An async queue that uses Redis as a storage.
+///
+/// It's meant for high-throughput task types that can afford to lose tasks,
+/// tasks are as durable as the persistence configuration of the Redis server.
+/// Every task is stored in a hash, pending tasks are indexed in sorted sets by `scheduled_at`
+/// (one set per task type and priority) and tasks are claimed by Lua scripts,
+/// so multiple workers never fetch the same task. Tasks are also indexed by their type and,
+/// if they are failed or dead, by their state, so they are found without scanning all tasks.
+///
+/// Keys are prefixed with `fang` by default, use different prefixes to keep multiple queues
+/// in the same Redis database. The queue doesn't support Redis Cluster.
+///
+///    ```rust
+///         let connection = RedisAsyncQueue::connection("redis://127.0.0.1/").await?;
+///
+///         let queue = RedisAsyncQueue::builder().connection(connection).build();
+///     ```
+///
+#[derive(TypedBuilder, Clone)]
+pub struct RedisAsyncQueue {
+    #[builder(setter(into))]
+    connection: ConnectionManager,
+    #[builder(default = DEFAULT_PREFIX.to_string(), setter(into))]
+    prefix: String,
+    #[builder(default, setter(skip))]
+    scripts: Arc<RedisScripts>,
+}
+
+impl RedisAsyncQueue {
+    /// Open a connection to the Redis server at `redis_url`. The connection reconnects automatically
+    /// and can be shared by all clones of the queue.
+    pub async fn connection(redis_url: &str) -> Result<ConnectionManager, AsyncQueueError> {
+        let client = redis::Client::open(redis_url)?;
+
+        Ok(ConnectionManager::new(client).await?)
+    }
+
+    fn task_key(&self, id: &str) -> String {
+        format!("{}:task:{}", self.prefix, id)
+    }
+
+    fn tasks_key(&self) -> String {
+        format!("{}:tasks", self.prefix)
+    }
+
+    fn type_key(&self, task_type: &str) -> String {
+        format!("{}:type:{}", self.prefix, task_type)
+    }
+
+    fn pending_key(&self, task_type: &str, priority: &str) -> String {
+        format!("{}:pending:{}:{}", self.prefix, task_type, priority)
+    }
+
+    fn priorities_key(&self, task_type: &str) -> String {
+        format!("{}:priorities:{}", self.prefix, task_type)
+    }
+
+    fn in_progress_key(&self) -> String {
+        format!("{}:in_progress", self.prefix)
+    }
+
+    fn state_key(&self, state: FangTaskState) -> String {
+        format!("{}:{}", self.prefix, state.as_str())
+    }
+
+    fn uniq_key(&self, uniq_hash: &str) -> String {
+        format!("{}:uniq:{}", self.prefix, uniq_hash)
+    }
+
+    /// The keys that scripts change for `tasks`, the keys shared by all tasks come first
+    /// and are followed by the keys of every task in the order of `task_keys` in `common.lua`
+    fn script_keys(&self, tasks: &[IndexedTask]) -> Vec<String> {
+        let mut keys = vec![
+            self.tasks_key(),
+            self.in_progress_key(),
+            self.state_key(FangTaskState::Failed),
+            self.state_key(FangTaskState::Dead),
+        ];
+
+        for task in tasks {
+            keys.push(self.task_key(&task.id));
+            keys.push(self.type_key(&task.task_type));
+            keys.push(self.pending_key(&task.task_type, &task.priority));
+            keys.push(self.priorities_key(&task.task_type));
+            keys.push(self.uniq_key(task.uniq_hash.as_deref().unwrap_or_default()));
+        }
+
+        keys
+    }
+
+    /// Read the fields that the keys of tasks are derived from, tasks that don't exist are skipped
+    async fn indexed_tasks(&mut self, ids: &[String]) -> Result<Vec<IndexedTask>, AsyncQueueError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipeline = redis::pipe();
+
+        for id in ids {
+            pipeline.cmd("HMGET").arg(self.task_key(id)).arg(&[
+                "task_type",
+                "priority",
+                "uniq_hash",
+            ]);
+        }
+
+        let fields: Vec<(Option<String>, Option<String>, Option<String>)> =
+            pipeline.query_async(&mut self.connection).await?;
+
+        Ok(ids
+            .iter()
+            .zip(fields)
+            .filter_map(|(id, (task_type, priority, uniq_hash))| {
+                Some(IndexedTask {
+                    id: id.clone(),
+                    task_type: task_type?,
+                    priority: priority?,
+                    uniq_hash,
+                })
+            })
+            .collect())
+    }
+
+    fn new_task(
+        task: &dyn AsyncRunnable,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<Task, AsyncQueueError> {
+        let metadata = serde_json::to_value(task)?;
+
+        let uniq_hash = if task.uniq() {
+            Some(AsyncQueue::<NoTls>::calculate_hash(metadata.to_string()))
+        } else {
+            None
+        };
+
+        let now = Utc::now();
+
+        Ok(Task::builder()
+            .id(Uuid::new_v4())
+            .metadata(metadata)
+            .error_message(None)
+            .task_type(task.task_type())
+            .uniq_hash(uniq_hash)
+            .retries(0)
+            .scheduled_at(scheduled_at)
+            .created_at(now)
+            .updated_at(now)
+            .priority(task.priority())
+            .build())
+    }
+
+    /// Insert `tasks` with one pipeline and return them,
+    /// a unique task is replaced with the pending task that has the same hash
+    async fn insert(
+        &mut self,
+        tasks: &[&dyn AsyncRunnable],
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<Vec<Task>, AsyncQueueError> {
+        let new_tasks = tasks
+            .iter()
+            .map(|task| Self::new_task(*task, scheduled_at))
+            .collect::<Result<Vec<Task>, AsyncQueueError>>()?;
+
+        if new_tasks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let invocations: Vec<ScriptInvocation> = new_tasks
+            .iter()
+            .map(|task| {
+                let mut invocation = self
+                    .scripts
+                    .insert_task
+                    .key(self.script_keys(&[IndexedTask::from(task)]));
+
+                invocation
+                    .arg(task.id.to_string())
+                    .arg(task.uniq_hash.as_deref().unwrap_or_default())
+                    .arg(Self::task_fields(task));
+
+                invocation
+            })
+            .collect();
+
+        let mut pipeline = redis::pipe();
+
+        pipeline.load_script(&self.scripts.insert_task).ignore();
+
+        for invocation in &invocations {
+            pipeline.invoke_script(invocation);
+        }
+
+        let ids: Vec<String> = pipeline.query_async(&mut self.connection).await?;
+
+        self.get_tasks(&ids).await
+    }
+
+    async fn insert_one(
+        &mut self,
+        task: &dyn AsyncRunnable,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<Task, AsyncQueueError> {
+        let mut tasks = self.insert(&[task], scheduled_at).await?;
+
+        tasks.pop().ok_or(AsyncQueueError::ResultError {
+            expected: 1,
+            found: 0,
+        })
+    }
+
+    /// Update the fields of the task if it's in the `expected_state`
+    /// and, if `expected_updated_at` is given, was last updated at that time.
+    /// Returns the number of updated tasks.
+    async fn update(
+        &mut self,
+        task: &Task,
+        expected_state: Option<FangTaskState>,
+        expected_updated_at: Option<DateTime<Utc>>,
+        fields: Vec<(&'static str, String)>,
+    ) -> Result<u64, AsyncQueueError> {
+        Ok(self
+            .scripts
+            .update_task
+            .key(self.script_keys(&[IndexedTask::from(task)]))
+            .arg(task.id.to_string())
+            .arg(
+                expected_state
+                    .map(|state| state.as_str())
+                    .unwrap_or_default(),
+            )
+            .arg(
+                expected_updated_at
+                    .map(|updated_at| updated_at.timestamp_micros().to_string())
+                    .unwrap_or_default(),
+            )
+            .arg(fields)
+            .invoke_async(&mut self.connection)
+            .await?)
+    }
+
+    async fn update_and_get(
+        &mut self,
+        task: &Task,
+        fields: Vec<(&'static str, String)>,
+    ) -> Result<Task, AsyncQueueError> {
+        let updated = self.update(task, None, None, fields).await?;
+
+        if updated != 1 {
+            return Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: updated,
+            });
+        }
+
+        self.get_task(&task.id.to_string()).await
+    }
+
+    /// Remove the tasks with `ids` that match `field` and `value`, see `remove_tasks.lua`
+    async fn remove(
+        &mut self,
+        ids: &[String],
+        field: &str,
+        value: String,
+    ) -> Result<u64, AsyncQueueError> {
+        let mut removed = 0;
+
+        for ids in ids.chunks(REMOVE_BATCH_SIZE) {
+            let tasks = self.indexed_tasks(ids).await?;
+
+            if tasks.is_empty() {
+                continue;
+            }
+
+            let removed_batch: u64 = self
+                .scripts
+                .remove_tasks
+                .key(self.script_keys(&tasks))
+                .arg(field)
+                .arg(&value)
+                .arg(tasks.iter().map(|task| &task.id).collect::<Vec<_>>())
+                .invoke_async(&mut self.connection)
+                .await?;
+
+            removed += removed_batch;
+        }
+
+        Ok(removed)
+    }
+
+    async fn get_task(&mut self, id: &str) -> Result<Task, AsyncQueueError> {
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(self.task_key(id))
+            .query_async(&mut self.connection)
+            .await?;
+
+        if fields.is_empty() {
+            return Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: 0,
+            });
+        }
+
+        Self::task_from_fields(fields)
+    }
+
+    async fn get_tasks(&mut self, ids: &[String]) -> Result<Vec<Task>, AsyncQueueError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipeline = redis::pipe();
+
+        for id in ids {
+            pipeline.hgetall(self.task_key(id));
+        }
+
+        let tasks: Vec<HashMap<String, String>> =
+            pipeline.query_async(&mut self.connection).await?;
+
+        // tasks can be removed after they are fetched
+        tasks
+            .into_iter()
+            .filter(|fields| !fields.is_empty())
+            .map(Self::task_from_fields)
+            .collect()
+    }
+
+    fn retry_fields(
+        retries: i32,
+        backoff_seconds: u32,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Vec<(&'static str, String)> {
+        let now = Utc::now();
+        let scheduled_at = now + Duration::seconds(backoff_seconds as i64);
+
+        vec![
+            ("state", FangTaskState::Retried.as_str().to_string()),
+            ("error_message", error_message.to_string()),
+            ("error_details", Self::error_details_field(error_details)),
+            ("retries", (retries + 1).to_string()),
+            ("scheduled_at", scheduled_at.timestamp_micros().to_string()),
+            ("updated_at", now.timestamp_micros().to_string()),
+        ]
+    }
+
+    /// The fields of a task that ends in `state`, `Failed` or `Dead`
+    fn fail_fields(
+        state: FangTaskState,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Vec<(&'static str, String)> {
+        vec![
+            ("state", state.as_str().to_string()),
+            ("error_message", error_message.to_string()),
+            ("error_details", Self::error_details_field(error_details)),
+            ("updated_at", Utc::now().timestamp_micros().to_string()),
+        ]
+    }
+
+    fn is_failed(task: &Task) -> bool {
+        task.state == FangTaskState::Failed || task.state == FangTaskState::Dead
+    }
+
+    fn retry_failed_fields() -> Vec<(&'static str, String)> {
+        let now = Utc::now().timestamp_micros().to_string();
+
+        vec![
+            ("state", FangTaskState::New.as_str().to_string()),
+            ("retries", 0.to_string()),
+            ("scheduled_at", now.clone()),
+            ("updated_at", now),
+        ]
+    }
+
+    /// Missing details are stored as JSON `null`, so they replace the details of a previous error
+    fn error_details_field(error_details: Option<&serde_json::Value>) -> String {
+        error_details
+            .unwrap_or(&serde_json::Value::Null)
+            .to_string()
+    }
+
+    fn task_fields(task: &Task) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("id", task.id.to_string()),
+            ("metadata", task.metadata.to_string()),
+            ("state", task.state.as_str().to_string()),
+            ("task_type", task.task_type.clone()),
+            ("retries", task.retries.to_string()),
+            (
+                "scheduled_at",
+                task.scheduled_at.timestamp_micros().to_string(),
+            ),
+            ("created_at", task.created_at.timestamp_micros().to_string()),
+            ("updated_at", task.updated_at.timestamp_micros().to_string()),
+            ("priority", task.priority.to_string()),
+        ];
+
+        if let Some(error_message) = &task.error_message {
+            fields.push(("error_message", error_message.clone()));
+        }
+
+        if let Some(error_details) = &task.error_details {
+            fields.push(("error_details", error_details.to_string()));
+        }
+
+        if let Some(uniq_hash) = &task.uniq_hash {
+            fields.push(("uniq_hash", uniq_hash.clone()));
+        }
+
+        if let Some(heartbeat_at) = task.heartbeat_at {
+            fields.push(("heartbeat_at", heartbeat_at.timestamp_micros().to_string()));
+        }
+
+        fields
+    }
+
+    fn task_from_fields(mut fields: HashMap<String, String>) -> Result<Task, AsyncQueueError> {
+        let mut field = |name: &str| {
+            fields
+                .remove(name)
+                .ok_or_else(|| invalid_task_error(format!("the field {} is missing", name)))
+        };
+
+        let id = field("id")?;
+        let metadata = field("metadata")?;
+        let state = field("state")?;
+        let task_type = field("task_type")?;
+        let retries = field("retries")?;
+        let scheduled_at = field("scheduled_at")?;
+        let created_at = field("created_at")?;
+        let updated_at = field("updated_at")?;
+        let priority = field("priority")?;
+        let error_message = field("error_message").ok();
+        let uniq_hash = field("uniq_hash").ok();
+        let heartbeat_at = field("heartbeat_at").ok();
+        let error_details = field("error_details")
+            .ok()
+            .map(|details| serde_json::from_str::<serde_json::Value>(&details))
+            .transpose()?
+            .filter(|details| !details.is_null());
+
+        Ok(Task::builder()
+            .id(Uuid::parse_str(&id).map_err(|error| invalid_task_error(error.to_string()))?)
+            .metadata(serde_json::from_str::<serde_json::Value>(&metadata)?)
+            .error_message(error_message)
+            .state(
+                FangTaskState::from_name(&state)
+                    .ok_or_else(|| invalid_task_error(format!("unknown state {}", state)))?,
+            )
+            .task_type(task_type)
+            .uniq_hash(uniq_hash)
+            .retries(parse_number::<i32>(&retries)?)
+            .scheduled_at(parse_timestamp(&scheduled_at)?)
+            .created_at(parse_timestamp(&created_at)?)
+            .updated_at(parse_timestamp(&updated_at)?)
+            .heartbeat_at(heartbeat_at.map(|at| parse_timestamp(&at)).transpose()?)
+            .priority(parse_number::<i16>(&priority)?)
+            .error_details(error_details)
+            .build())
+    }
+}
+
+fn invalid_task_error(detail: String) -> AsyncQueueError {
+    AsyncQueueError::RedisError(RedisError::from((
+        ErrorKind::TypeError,
+        "Invalid task",
+        detail,
+    )))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, AsyncQueueError> {
+    value
+        .parse::<T>()
+        .map_err(|_| invalid_task_error(format!("{} is not a valid number", value)))
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, AsyncQueueError> {
+    DateTime::from_timestamp_micros(parse_number::<i64>(value)?)
+        .ok_or_else(|| invalid_task_error(format!("{} is not a valid timestamp", value)))
+}
+
+#[async_trait]
+impl AsyncQueueable for RedisAsyncQueue {
+    async fn fetch_and_touch_task(
+        &mut self,
+        task_type: Option<String>,
+    ) -> Result<Option<Task>, AsyncQueueError> {
+        let mut tasks = self.fetch_and_touch_tasks(task_type, 1).await?;
+
+        Ok(tasks.pop())
+    }
+
+    async fn fetch_and_touch_tasks(
+        &mut self,
+        task_type: Option<String>,
+        limit: u32,
+    ) -> Result<Vec<Task>, AsyncQueueError> {
+        let task_type = task_type.unwrap_or_else(|| DEFAULT_TASK_TYPE.to_string());
+        let limit = limit as usize;
+
+        loop {
+            let now = Utc::now().timestamp_micros();
+
+            let priorities: Vec<String> = redis::cmd("ZRANGE")
+                .arg(self.priorities_key(&task_type))
+                .arg(0)
+                .arg(-1)
+                .query_async(&mut self.connection)
+                .await?;
+
+            let mut candidates: Vec<String> = Vec::new();
+
+            for priority in priorities {
+                if candidates.len() >= limit {
+                    break;
+                }
+
+                let pending: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+                    .arg(self.pending_key(&task_type, &priority))
+                    .arg("-inf")
+                    .arg(now)
+                    .arg("LIMIT")
+                    .arg(0)
+                    .arg(limit - candidates.len())
+                    .query_async(&mut self.connection)
+                    .await?;
+
+                candidates.extend(pending);
+            }
+
+            let tasks = self.indexed_tasks(&candidates).await?;
+
+            if tasks.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let ids: Vec<String> = self
+                .scripts
+                .fetch_and_touch_tasks
+                .key(self.script_keys(&tasks))
+                .arg(now)
+                .arg(tasks.iter().map(|task| &task.id).collect::<Vec<_>>())
+                .invoke_async(&mut self.connection)
+                .await?;
+
+            // all candidates were claimed by other workers, the next ones are selected
+            if !ids.is_empty() {
+                return self.get_tasks(&ids).await;
+            }
+        }
+    }
+
+    async fn insert_task(&mut self, task: &dyn AsyncRunnable) -> Result<Task, AsyncQueueError> {
+        self.insert_one(task, Utc::now()).await
+    }
+
+    async fn insert_tasks(
+        &mut self,
+        tasks: &[&dyn AsyncRunnable],
+    ) -> Result<Vec<Task>, AsyncQueueError> {
+        self.insert(tasks, Utc::now()).await
+    }
+
+    async fn remove_all_tasks(&mut self) -> Result<u64, AsyncQueueError> {
+        let ids: Vec<String> = redis::cmd("ZRANGE")
+            .arg(self.tasks_key())
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut self.connection)
+            .await?;
+
+        self.remove(&ids, "all", String::new()).await
+    }
+
+    async fn remove_all_scheduled_tasks(&mut self) -> Result<u64, AsyncQueueError> {
+        let now = Utc::now().timestamp_micros();
+
+        let ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(self.tasks_key())
+            .arg(format!("({}", now))
+            .arg("+inf")
+            .query_async(&mut self.connection)
+            .await?;
+
+        self.remove(&ids, "scheduled_at", now.to_string()).await
+    }
+
+    async fn remove_task(&mut self, id: Uuid) -> Result<u64, AsyncQueueError> {
+        let id = id.to_string();
+        let removed = self
+            .remove(std::slice::from_ref(&id), "id", id.clone())
+            .await?;
+
+        if removed != 1 {
+            return Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: removed,
+            });
+        }
+
+        Ok(removed)
+    }
+
+    async fn remove_task_by_metadata(
+        &mut self,
+        task: &dyn AsyncRunnable,
+    ) -> Result<u64, AsyncQueueError> {
+        if task.uniq() {
+            let metadata = serde_json::to_value(task)?;
+            let uniq_hash = AsyncQueue::<NoTls>::calculate_hash(metadata.to_string());
+
+            let id: Option<String> = redis::cmd("GET")
+                .arg(self.uniq_key(&uniq_hash))
+                .query_async(&mut self.connection)
+                .await?;
+
+            match id {
+                Some(id) => self.remove(&[id], "uniq_hash", uniq_hash).await,
+                None => Ok(0),
+            }
+        } else {
+            Err(AsyncQueueError::TaskNotUniqError)
+        }
+    }
+
+    async fn remove_tasks_type(&mut self, task_type: &str) -> Result<u64, AsyncQueueError> {
+        let ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(self.type_key(task_type))
+            .query_async(&mut self.connection)
+            .await?;
+
+        self.remove(&ids, "task_type", task_type.to_string()).await
+    }
+
+    async fn find_task_by_id(&mut self, id: Uuid) -> Result<Task, AsyncQueueError> {
+        self.get_task(&id.to_string()).await
+    }
+
+    async fn update_task_state(
+        &mut self,
+        task: Task,
+        state: FangTaskState,
+    ) -> Result<Task, AsyncQueueError> {
+        let fields = vec![
+            ("state", state.as_str().to_string()),
+            ("updated_at", Utc::now().timestamp_micros().to_string()),
+        ];
+
+        self.update_and_get(&task, fields).await
+    }
+
+    async fn fail_task(&mut self, task: Task, error: &FangError) -> Result<Task, AsyncQueueError> {
+        self.update_and_get(
+            &task,
+            Self::fail_fields(
+                FangTaskState::Failed,
+                &error.description,
+                error.details.as_ref(),
+            ),
+        )
+        .await
+    }
+
+    async fn dead_letter_task(
+        &mut self,
+        task: Task,
+        error: &FangError,
+    ) -> Result<Task, AsyncQueueError> {
+        self.update_and_get(
+            &task,
+            Self::fail_fields(
+                FangTaskState::Dead,
+                &error.description,
+                error.details.as_ref(),
+            ),
+        )
+        .await
+    }
+
+    async fn schedule_task(&mut self, task: &dyn AsyncRunnable) -> Result<Task, AsyncQueueError> {
+        let scheduled_at = AsyncQueue::<NoTls>::calculate_scheduled_at(task)?;
+
+        self.insert_one(task, scheduled_at).await
+    }
+
+    async fn schedule_retry(
+        &mut self,
+        task: &Task,
+        backoff_seconds: u32,
+        error: &FangError,
+    ) -> Result<Task, AsyncQueueError> {
+        self.update_and_get(
+            task,
+            Self::retry_fields(
+                task.retries,
+                backoff_seconds,
+                &error.description,
+                error.details.as_ref(),
+            ),
+        )
+        .await
+    }
+
+    async fn heartbeat_task(&mut self, task: &Task) -> Result<u64, AsyncQueueError> {
+        let fields = vec![("heartbeat_at", Utc::now().timestamp_micros().to_string())];
+
+        self.update(
+            task,
+            Some(FangTaskState::InProgress),
+            Some(task.updated_at),
+            fields,
+        )
+        .await
+    }
+
+    async fn reap_expired_tasks(
+        &mut self,
+        heartbeat_timeout: std::time::Duration,
+    ) -> Result<u64, AsyncQueueError> {
+        let heartbeat_timeout =
+            Duration::from_std(heartbeat_timeout).map_err(|_| AsyncQueueError::TimeError)?;
+        let expired_at = Utc::now() - heartbeat_timeout;
+
+        let ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(self.in_progress_key())
+            .arg("-inf")
+            .arg(format!("({}", expired_at.timestamp_micros()))
+            .query_async(&mut self.connection)
+            .await?;
+
+        let mut reaped = 0;
+
+        for task in self.get_tasks(&ids).await? {
+            let fields =
+                match serde_json::from_value::<Box<dyn AsyncRunnable>>(task.metadata.clone()) {
+                    Ok(runnable) if task.retries < runnable.max_retries() => {
+                        let backoff_seconds = runnable.backoff(task.retries as u32);
+
+                        Self::retry_fields(
+                            task.retries,
+                            backoff_seconds,
+                            EXPIRED_HEARTBEAT_ERROR,
+                            None,
+                        )
+                    }
+                    Ok(_) => Self::fail_fields(FangTaskState::Dead, EXPIRED_HEARTBEAT_ERROR, None),
+                    Err(_) => {
+                        Self::fail_fields(FangTaskState::Failed, EXPIRED_HEARTBEAT_ERROR, None)
+                    }
+                };
+
+            // the task could be finished or reaped by another worker in the meantime
+            reaped += self
+                .update(
+                    &task,
+                    Some(FangTaskState::InProgress),
+                    Some(task.updated_at),
+                    fields,
+                )
+                .await?;
+        }
+
+        Ok(reaped)
+    }
+
+    async fn retry_failed_task(&mut self, id: Uuid) -> Result<Task, AsyncQueueError> {
+        let task = self.get_task(&id.to_string()).await?;
+
+        let updated = if Self::is_failed(&task) {
+            // the task could be retried by another client in the meantime
+            self.update(
+                &task,
+                Some(task.state.clone()),
+                None,
+                Self::retry_failed_fields(),
+            )
+            .await?
+        } else {
+            0
+        };
+
+        if updated != 1 {
+            return Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: updated,
+            });
+        }
+
+        self.get_task(&id.to_string()).await
+    }
+
+    async fn retry_failed_tasks_of_type(
+        &mut self,
+        task_type: &str,
+    ) -> Result<u64, AsyncQueueError> {
+        let type_key = self.type_key(task_type);
+
+        let (failed_ids, dead_ids): (Vec<String>, Vec<String>) = redis::pipe()
+            .sinter(&[&type_key, &self.state_key(FangTaskState::Failed)])
+            .sinter(&[&type_key, &self.state_key(FangTaskState::Dead)])
+            .query_async(&mut self.connection)
+            .await?;
+
+        let ids: Vec<String> = failed_ids.into_iter().chain(dead_ids).collect();
+        let mut retried = 0;
+
+        for task in self.get_tasks(&ids).await? {
+            if task.task_type != task_type || !Self::is_failed(&task) {
+                continue;
+            }
+
+            // the task could be retried by another client in the meantime
+            retried += self
+                .update(
+                    &task,
+                    Some(task.state.clone()),
+                    None,
+                    Self::retry_failed_fields(),
+                )
+                .await?;
+        }
+
+        Ok(retried)
+    }
+
+    async fn discard_dead_tasks(
+        &mut self,
+        older_than: std::time::Duration,
+    ) -> Result<u64, AsyncQueueError> {
+        let older_than = Duration::from_std(older_than).map_err(|_| AsyncQueueError::TimeError)?;
+        let updated_before = Utc::now() - older_than;
+
+        let ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(self.state_key(FangTaskState::Dead))
+            .query_async(&mut self.connection)
+            .await?;
+
+        self.remove(&ids, "dead", updated_before.timestamp_micros().to_string())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod async_redis_queue_tests {
+    use super::RedisAsyncQueue;
+    use crate::asynk::async_queue::AsyncQueueError;
+    use crate::asynk::async_queue::AsyncQueueable;
+    use crate::asynk::async_queue::FangTaskState;
+    use crate::asynk::async_worker::AsyncWorker;
+    use crate::asynk::AsyncRunnable;
+    use crate::FangError;
+    use crate::RetentionMode;
+    use crate::Scheduled;
+    use crate::TaskContext;
+    use crate::EXPIRED_HEARTBEAT_ERROR;
+    use async_trait::async_trait;
+    use chrono::DateTime;
+    use chrono::Duration;
+    use chrono::SubsecRound;
+    use chrono::Utc;
+    use serde::{Deserialize, Serialize};
+    use std::env;
+    use tokio_util::sync::CancellationToken;
+    use uuid::Uuid;
+
+    #[derive(Serialize, Deserialize)]
+    struct RedisTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for RedisTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RedisUrgentTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for RedisUrgentTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn priority(&self) -> i16 {
+            10
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RedisUniqTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for RedisUniqTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn uniq(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RedisScheduledTask {
+        pub number: u16,
+        pub datetime: String,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for RedisScheduledTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn cron(&self) -> Option<Scheduled> {
+            let datetime = self.datetime.parse::<DateTime<Utc>>().ok()?;
+            Some(Scheduled::ScheduleOnce(datetime))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct RedisFailingTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl AsyncRunnable for RedisFailingTask {
+        async fn run(
+            &self,
+            _queueable: &mut dyn AsyncQueueable,
+            _context: &TaskContext,
+        ) -> Result<(), FangError> {
+            Err(FangError::retryable("Failed"))
+        }
+
+        fn task_type(&self) -> String {
+            "redis_failing".to_string()
+        }
+
+        fn max_retries(&self) -> i32 {
+            1
+        }
+    }
+
+    #[tokio::test]
+    async fn insert_task_creates_new_task() {
+        let mut queue = queue().await;
+
+        let task = queue.insert_task(&RedisTask { number: 1 }).await.unwrap();
+
+        assert_eq!(Some(1), task.metadata["number"].as_u64());
+        assert_eq!(Some("RedisTask"), task.metadata["type"].as_str());
+        assert_eq!(FangTaskState::New, task.state);
+        assert_eq!(None, task.uniq_hash);
+        assert_eq!(0, task.retries);
+        assert_eq!(task, queue.find_task_by_id(task.id).await.unwrap());
+
+        queue.remove_all_tasks().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn insert_task_does_not_duplicate_uniq_tasks() {
+        let mut queue = queue().await;
+
+        let task1 = queue
+            .insert_task(&RedisUniqTask { number: 1 })
+            .await
+            .unwrap();
+        let task2 = queue
+            .insert_task(&RedisUniqTask { number: 1 })
+            .await
+            .unwrap();
+        let tasks = queue
+            .insert_tasks(&[
+                &RedisUniqTask { number: 1 },
+                &RedisUniqTask { number: 2 },
+                &RedisUniqTask { number: 2 },
+            ])
+            .await
+            .unwrap();
+
+        assert!(task1.uniq_hash.is_some());
+        assert_eq!(task1.id, task2.id);
+        assert_eq!(task1.id, tasks[0].id);
+        assert_eq!(tasks[1].id, tasks[2].id);
+
+        queue
+            .update_task_state(task1.clone(), FangTaskState::Finished)
+            .await
+            .unwrap();
+
+        let task3 = queue
+            .insert_task(&RedisUniqTask { number: 1 })
+            .await
+            .unwrap();
+
+        assert_ne!(task1.id, task3.id);
+        assert_eq!(3, queue.remove_all_tasks().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn fetch_and_touch_tasks_respects_priority() {
+        let mut queue = queue().await;
+
+        let task1 = queue.insert_task(&RedisTask { number: 1 }).await.unwrap();
+        let task2 = queue
+            .insert_task(&RedisUrgentTask { number: 2 })
+            .await
+            .unwrap();
+        queue.insert_task(&RedisTask { number: 3 }).await.unwrap();
+
+        let tasks = queue.fetch_and_touch_tasks(None, 2).await.unwrap();
+
+        assert_eq!(2, tasks.len());
+        assert_eq!(task2.id, tasks[0].id);
+        assert_eq!(task1.id, tasks[1].id);
+        assert_eq!(FangTaskState::InProgress, tasks[0].state);
+        assert!(tasks[0].heartbeat_at.is_some());
+
+        let tasks = queue.fetch_and_touch_tasks(None, 2).await.unwrap();
+
+        assert_eq!(1, tasks.len());
+        assert_eq!(None, queue.fetch_and_touch_task(None).await.unwrap());
+        assert_eq!(
+            None,
+            queue
+                .fetch_and_touch_task(Some("other_type".to_string()))
+                .await
+                .unwrap()
+        );
+
+        queue.remove_all_tasks().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn schedule_task_test() {
+        let mut queue = queue().await;
+
+        let datetime = (Utc::now() + Duration::seconds(7)).round_subsecs(0);
+
+        let task = queue
+            .schedule_task(&RedisScheduledTask {
+                number: 1,
+                datetime: datetime.to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(datetime, task.scheduled_at);
+        assert_eq!(None, queue.fetch_and_touch_task(None).await.unwrap());
+        assert_eq!(1, queue.remove_all_scheduled_tasks().await.unwrap());
+        assert_eq!(0, queue.remove_all_tasks().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn schedule_retry_and_fail_test() {
+        let mut queue = queue().await;
+
+        queue.insert_task(&RedisTask { number: 1 }).await.unwrap();
+
+        let task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        let retried_task = queue
+            .schedule_retry(
+                &task,
+                60,
+                &FangError::retryable("Failed").with_details(serde_json::json!({"code": 42})),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(FangTaskState::Retried, retried_task.state);
+        assert_eq!(1, retried_task.retries);
+        assert_eq!(Some("Failed".to_string()), retried_task.error_message);
+        assert_eq!(
+            Some(serde_json::json!({"code": 42})),
+            retried_task.error_details
+        );
+        assert!(retried_task.scheduled_at > Utc::now() + Duration::seconds(50));
+        assert_eq!(None, queue.fetch_and_touch_task(None).await.unwrap());
+
+        let retried_task = queue
+            .schedule_retry(&retried_task, 0, &FangError::retryable("Failed"))
+            .await
+            .unwrap();
+        let fetched_task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        assert_eq!(retried_task.id, fetched_task.id);
+
+        let failed_task = queue
+            .fail_task(fetched_task, &FangError::retryable("Some error"))
+            .await
+            .unwrap();
+
+        assert_eq!(FangTaskState::Failed, failed_task.state);
+        assert_eq!(Some("Some error".to_string()), failed_task.error_message);
+        assert_eq!(None, failed_task.error_details);
+
+        queue.remove_all_tasks().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove_tasks_test() {
+        let mut queue = queue().await;
+
+        let task = queue.insert_task(&RedisTask { number: 1 }).await.unwrap();
+        queue
+            .insert_task(&RedisUniqTask { number: 1 })
+            .await
+            .unwrap();
+        queue
+            .insert_task(&RedisFailingTask { number: 1 })
+            .await
+            .unwrap();
+
+        assert_eq!(1, queue.remove_task(task.id).await.unwrap());
+        assert!(matches!(
+            queue.remove_task(task.id).await,
+            Err(AsyncQueueError::ResultError {
+                expected: 1,
+                found: 0
+            })
+        ));
+        assert!(matches!(
+            queue
+                .remove_task_by_metadata(&RedisTask { number: 1 })
+                .await,
+            Err(AsyncQueueError::TaskNotUniqError)
+        ));
+        assert_eq!(
+            1,
+            queue
+                .remove_task_by_metadata(&RedisUniqTask { number: 1 })
+                .await
+                .unwrap()
+        );
+        assert_eq!(1, queue.remove_tasks_type("redis_failing").await.unwrap());
+        assert!(queue.find_task_by_id(task.id).await.is_err());
+        assert_eq!(0, queue.remove_all_tasks().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn retry_failed_tasks_of_type_and_discard_dead_tasks_test() {
+        let mut queue = queue().await;
+
+        let tasks = queue
+            .insert_tasks(&[
+                &RedisFailingTask { number: 1 },
+                &RedisFailingTask { number: 2 },
+                &RedisTask { number: 3 },
+            ])
+            .await
+            .unwrap();
+
+        let error = FangError::retryable("Failed");
+        let failed_task = queue.fail_task(tasks[0].clone(), &error).await.unwrap();
+        let dead_task = queue
+            .dead_letter_task(tasks[1].clone(), &error)
+            .await
+            .unwrap();
+        let other_type_task = queue.fail_task(tasks[2].clone(), &error).await.unwrap();
+
+        assert_eq!(
+            2,
+            queue
+                .retry_failed_tasks_of_type("redis_failing")
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            FangTaskState::New,
+            queue.find_task_by_id(failed_task.id).await.unwrap().state
+        );
+        assert_eq!(
+            FangTaskState::Failed,
+            queue
+                .find_task_by_id(other_type_task.id)
+                .await
+                .unwrap()
+                .state
+        );
+
+        let dead_task = queue.dead_letter_task(dead_task, &error).await.unwrap();
+
+        assert_eq!(
+            0,
+            queue
+                .discard_dead_tasks(std::time::Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        assert_eq!(
+            1,
+            queue
+                .discard_dead_tasks(std::time::Duration::from_millis(1))
+                .await
+                .unwrap()
+        );
+        assert!(queue.find_task_by_id(dead_task.id).await.is_err());
+        assert_eq!(2, queue.remove_all_tasks().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reap_expired_tasks_test() {
+        let mut queue = queue().await;
+
+        queue.insert_task(&RedisTask { number: 1 }).await.unwrap();
+
+        let task = queue.fetch_and_touch_task(None).await.unwrap().unwrap();
+
+        assert_eq!(1, queue.heartbeat_task(&task).await.unwrap());
+        assert_eq!(
+            0,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_secs(60))
+                .await
+                .unwrap()
+        );
+
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+
+        assert_eq!(
+            1,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_millis(1))
+                .await
+                .unwrap()
+        );
+
+        let reaped_task = queue.find_task_by_id(task.id).await.unwrap();
+
+        assert_eq!(FangTaskState::Retried, reaped_task.state);
+        assert_eq!(1, reaped_task.retries);
+        assert_eq!(
+            Some(EXPIRED_HEARTBEAT_ERROR.to_string()),
+            reaped_task.error_message
+        );
+        assert_eq!(0, queue.heartbeat_task(&reaped_task).await.unwrap());
+
+        queue.remove_all_tasks().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn worker_executes_tasks() {
+        let mut queue = queue().await;
+
+        let task = queue.insert_task(&RedisTask { number: 1 }).await.unwrap();
+        let failing_task = queue
+            .insert_task(&RedisFailingTask { number: 1 })
+            .await
+            .unwrap();
+
+        let shutdown_token = CancellationToken::new();
+
+        let mut worker: AsyncWorker<RedisAsyncQueue> = AsyncWorker::builder()
+            .queue(queue.clone())
+            .retention_mode(RetentionMode::KeepAll)
+            .shutdown_token(shutdown_token.clone())
+            .build();
+
+        let mut failing_worker: AsyncWorker<RedisAsyncQueue> = AsyncWorker::builder()
+            .queue(queue.clone())
+            .task_type("redis_failing")
+            .retention_mode(RetentionMode::KeepAll)
+            .shutdown_token(shutdown_token.clone())
+            .build();
+
+        let join_handle = tokio::spawn(async move { worker.run_tasks().await });
+        let failing_join_handle = tokio::spawn(async move { failing_worker.run_tasks().await });
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        shutdown_token.cancel();
+
+        assert!(join_handle.await.unwrap().is_ok());
+        assert!(failing_join_handle.await.unwrap().is_ok());
+
+        let task = queue.find_task_by_id(task.id).await.unwrap();
+        let failing_task = queue.find_task_by_id(failing_task.id).await.unwrap();
+
+        assert_eq!(FangTaskState::Finished, task.state);
+        assert_eq!(FangTaskState::Retried, failing_task.state);
+        assert_eq!(1, failing_task.retries);
+        assert_eq!(Some("Failed".to_string()), failing_task.error_message);
+
+        queue.remove_all_tasks().await.unwrap();
+    }
+
+    // every test uses its own prefix, so tests don't see each other's tasks
+    async fn queue() -> RedisAsyncQueue {
+        let redis_url = env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+
+        let connection = RedisAsyncQueue::connection(&redis_url).await.unwrap();
+
+        RedisAsyncQueue::builder()
+            .connection(connection)
+            .prefix(format!("fang_test_{}", Uuid::new_v4()))
+            .build()
+    }
+}
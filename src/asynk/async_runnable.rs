@@ -2,19 +2,28 @@ use crate::async_queue::AsyncQueueError;
 use crate::asynk::async_queue::AsyncQueueable;
 use crate::FangError;
 use crate::Scheduled;
+use crate::TaskContext;
 use async_trait::async_trait;
 use bb8_postgres::bb8::RunError;
 use bb8_postgres::tokio_postgres::Error as TokioPostgresError;
 use serde_json::Error as SerdeError;
+use std::time::Duration;
 
 const COMMON_TYPE: &str = "common";
 pub const RETRIES_NUMBER: i32 = 20;
 
 impl From<AsyncQueueError> for FangError {
     fn from(error: AsyncQueueError) -> Self {
-        let message = format!("{:?}", error);
-        FangError {
-            description: message,
+        let message = error.to_string();
+
+        match error {
+            // invalid data stays invalid on the next attempt
+            AsyncQueueError::SerdeError(_) => FangError::permanent(message).with_source(error),
+            // the record the task works with was deleted
+            AsyncQueueError::ResultError { found: 0, .. } => {
+                FangError::permanent(message).with_source(error)
+            }
+            _ => FangError::retryable(message).with_source(error),
         }
     }
 }
@@ -41,8 +50,14 @@ impl From<SerdeError> for FangError {
 #[typetag::serde(tag = "type")]
 #[async_trait]
 pub trait AsyncRunnable: Send + Sync {
-    /// Execute the task. This method should define its logic
-    async fn run(&self, client: &mut dyn AsyncQueueable) -> Result<(), FangError>;
+    /// Execute the task. This method should define its logic.
+    ///
+    /// The `context` describes the current execution of the task, for example the number of its retries
+    async fn run(
+        &self,
+        client: &mut dyn AsyncQueueable,
+        context: &TaskContext,
+    ) -> Result<(), FangError>;
 
     /// Define the type of the task.
     /// The `common` task type is used by default
@@ -89,4 +104,20 @@ pub trait AsyncRunnable: Send + Sync {
     fn backoff(&self, attempt: u32) -> u32 {
         u32::pow(2, attempt)
     }
+
+    /// Define the priority of the task.
+    /// Tasks with a higher priority are executed before tasks with a lower priority
+    /// of the same `task_type`, tasks with the same priority are executed in the order they were scheduled.
+    /// By default, the priority is 0.
+    fn priority(&self) -> i16 {
+        0
+    }
+
+    /// Define the maximum duration of the task execution.
+    /// If the task is not finished in time, it fails with a timeout error and it's retried
+    /// the same way as any other failed task.
+    /// By default, there is no timeout.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
 }
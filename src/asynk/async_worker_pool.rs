@@ -1,11 +1,16 @@
 use crate::asynk::async_queue::AsyncQueueable;
 use crate::asynk::async_queue::DEFAULT_TASK_TYPE;
 use crate::asynk::async_worker::AsyncWorker;
+use crate::asynk::async_worker::DEFAULT_SHUTDOWN_TIMEOUT;
+use crate::Extensions;
 use crate::FangError;
-use crate::{RetentionMode, SleepParams};
+use crate::{RetentionMode, SleepParams, DEFAULT_HEARTBEAT_INTERVAL};
 use async_recursion::async_recursion;
 use log::error;
+use log::info;
+use std::time::Duration;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use typed_builder::TypedBuilder;
 
 #[derive(TypedBuilder, Clone)]
@@ -28,6 +33,49 @@ where
     /// The type of tasks that will be executed by `AsyncWorkerPool`.
     #[builder(default=DEFAULT_TASK_TYPE.to_string(), setter(into))]
     pub task_type: String,
+    /// shutdown_timeout controls how long running tasks are allowed to finish
+    /// after `AsyncWorkerPoolHandle::shutdown` is called
+    #[builder(default=DEFAULT_SHUTDOWN_TIMEOUT, setter(into))]
+    pub shutdown_timeout: Duration,
+    /// heartbeat_interval controls how often workers update the heartbeat of the task they are executing
+    #[builder(default=DEFAULT_HEARTBEAT_INTERVAL, setter(into))]
+    pub heartbeat_interval: Duration,
+    /// the maximum number of tasks every worker fetches in one go and executes concurrently.
+    /// By default, workers fetch and execute tasks one by one
+    #[builder(default = 1, setter(into))]
+    pub batch_size: u32,
+    /// the shared state, for example connection pools or HTTP clients, that is passed to every task
+    /// executed by the pool in its `TaskContext`
+    #[builder(default, setter(into))]
+    pub extensions: Extensions,
+    /// record_attempts controls if workers save every execution of a task,
+    /// see `AsyncQueueable::find_task_attempts`
+    #[builder(default, setter(into))]
+    pub record_attempts: bool,
+}
+
+/// A handle to the workers started by `AsyncWorkerPool::start`.
+/// It can be used to stop them gracefully.
+pub struct AsyncWorkerPoolHandle {
+    shutdown_token: CancellationToken,
+    join_handles: Vec<JoinHandle<()>>,
+}
+
+impl AsyncWorkerPoolHandle {
+    /// Stops all workers of the pool.
+    ///
+    /// Workers stop fetching new tasks immediately. Tasks that are already running can finish
+    /// during the `shutdown_timeout` of the pool, after that they are interrupted and put back into the queue.
+    /// The returned future resolves once all workers have exited.
+    pub async fn shutdown(self) {
+        self.shutdown_token.cancel();
+
+        for join_handle in self.join_handles {
+            if let Err(error) = join_handle.await {
+                error!("Failed to stop a worker {:?}", error);
+            }
+        }
+    }
 }
 
 impl<AQueue> AsyncWorkerPool<AQueue>
@@ -36,54 +84,71 @@ where
 {
     /// Starts the configured number of workers
     /// This is necessary in order to execute tasks.
-    pub async fn start(&mut self) {
-        for idx in 0..self.number_of_workers {
-            let pool = self.clone();
-            tokio::spawn(Self::supervise_task(pool, 0, idx));
+    ///
+    /// Use the returned `AsyncWorkerPoolHandle` to stop the workers.
+    pub async fn start(&mut self) -> AsyncWorkerPoolHandle {
+        let shutdown_token = CancellationToken::new();
+
+        let join_handles = (0..self.number_of_workers)
+            .map(|idx| {
+                let pool = self.clone();
+                tokio::spawn(Self::supervise_task(pool, shutdown_token.clone(), 0, idx))
+            })
+            .collect();
+
+        AsyncWorkerPoolHandle {
+            shutdown_token,
+            join_handles,
         }
     }
 
     #[async_recursion]
-    async fn supervise_task(pool: AsyncWorkerPool<AQueue>, restarts: u64, worker_number: u32) {
+    async fn supervise_task(
+        pool: AsyncWorkerPool<AQueue>,
+        shutdown_token: CancellationToken,
+        restarts: u64,
+        worker_number: u32,
+    ) {
         let restarts = restarts + 1;
-        let join_handle = Self::spawn_worker(
-            pool.queue.clone(),
-            pool.sleep_params.clone(),
-            pool.retention_mode.clone(),
-            pool.task_type.clone(),
-        )
-        .await;
+        let join_handle = Self::spawn_worker(pool.clone(), shutdown_token.clone()).await;
 
         if (join_handle.await).is_err() {
+            if shutdown_token.is_cancelled() {
+                error!("Worker {} stopped during the shutdown", worker_number);
+                return;
+            }
+
             error!(
                 "Worker {} stopped. Restarting. the number of restarts {}",
                 worker_number, restarts,
             );
-            Self::supervise_task(pool, restarts, worker_number).await;
+            Self::supervise_task(pool, shutdown_token, restarts, worker_number).await;
+        } else if shutdown_token.is_cancelled() {
+            info!("Worker {} stopped", worker_number);
         }
     }
 
     async fn spawn_worker(
-        queue: AQueue,
-        sleep_params: SleepParams,
-        retention_mode: RetentionMode,
-        task_type: String,
+        pool: AsyncWorkerPool<AQueue>,
+        shutdown_token: CancellationToken,
     ) -> JoinHandle<Result<(), FangError>> {
-        tokio::spawn(async move {
-            Self::run_worker(queue, sleep_params, retention_mode, task_type).await
-        })
+        tokio::spawn(async move { Self::run_worker(pool, shutdown_token).await })
     }
     async fn run_worker(
-        queue: AQueue,
-        sleep_params: SleepParams,
-        retention_mode: RetentionMode,
-        task_type: String,
+        pool: AsyncWorkerPool<AQueue>,
+        shutdown_token: CancellationToken,
     ) -> Result<(), FangError> {
         let mut worker: AsyncWorker<AQueue> = AsyncWorker::builder()
-            .queue(queue)
-            .sleep_params(sleep_params)
-            .retention_mode(retention_mode)
-            .task_type(task_type)
+            .queue(pool.queue)
+            .sleep_params(pool.sleep_params)
+            .retention_mode(pool.retention_mode)
+            .task_type(pool.task_type)
+            .shutdown_token(shutdown_token)
+            .shutdown_timeout(pool.shutdown_timeout)
+            .heartbeat_interval(pool.heartbeat_interval)
+            .batch_size(pool.batch_size)
+            .extensions(pool.extensions)
+            .record_attempts(pool.record_attempts)
             .build();
 
         worker.run_tasks().await
@@ -0,0 +1 @@
+DELETE FROM "fang_tasks" WHERE state = 'dead' AND updated_at < $1
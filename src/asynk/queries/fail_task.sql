@@ -1 +1 @@
-UPDATE "fang_tasks" SET "state" = $1 , "error_message" = $2 , "updated_at" = $3 WHERE id = $4 RETURNING *
+UPDATE "fang_tasks" SET "state" = $1 , "error_message" = $2 , "error_details" = $3 , "updated_at" = $4 WHERE id = $5 RETURNING *
@@ -0,0 +1 @@
+UPDATE "fang_tasks" SET "state" = 'in_progress' , "heartbeat_at" = $2 , "updated_at" = $2 WHERE id = ANY(ARRAY(SELECT id FROM fang_tasks WHERE task_type = $1 AND state in ('new', 'retried') AND $2 >= scheduled_at ORDER BY priority DESC, scheduled_at ASC, created_at ASC LIMIT $3 FOR UPDATE SKIP LOCKED)) RETURNING *
@@ -0,0 +1 @@
+SELECT * FROM fang_tasks WHERE state = 'in_progress' AND COALESCE(heartbeat_at, updated_at) < $1 FOR UPDATE SKIP LOCKED
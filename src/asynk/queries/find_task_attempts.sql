@@ -0,0 +1 @@
+SELECT * FROM fang_task_attempts WHERE task_id = $1 ORDER BY started_at ASC
@@ -0,0 +1 @@
+SELECT * FROM fang_tasks WHERE uniq_hash = ANY($1) AND state in ('new', 'retried')
@@ -0,0 +1 @@
+UPDATE "fang_tasks" SET "heartbeat_at" = $1 WHERE id = $2 AND state = 'in_progress' AND updated_at = $3
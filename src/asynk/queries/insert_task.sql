@@ -1 +1 @@
-INSERT INTO "fang_tasks" ("metadata", "task_type", "scheduled_at") VALUES ($1, $2, $3) RETURNING *
+INSERT INTO "fang_tasks" ("metadata", "task_type", "scheduled_at", "priority") VALUES ($1, $2, $3, $4) RETURNING *
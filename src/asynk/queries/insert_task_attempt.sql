@@ -0,0 +1 @@
+INSERT INTO "fang_task_attempts" ("id", "task_id", "attempt", "worker_id", "outcome", "error_message", "error_details", "started_at", "finished_at") VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
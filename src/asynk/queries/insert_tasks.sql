@@ -0,0 +1 @@
+INSERT INTO "fang_tasks" ("id", "metadata", "task_type", "uniq_hash", "scheduled_at", "priority") SELECT * FROM UNNEST($1::uuid[], $2::jsonb[], $3::varchar[], $4::text[], $5::timestamptz[], $6::int2[]) AS new_tasks ("id", "metadata", "task_type", "uniq_hash", "scheduled_at", "priority") WHERE new_tasks.uniq_hash IS NULL OR NOT EXISTS (SELECT 1 FROM "fang_tasks" WHERE "fang_tasks"."uniq_hash" = new_tasks.uniq_hash AND "fang_tasks"."state" IN ('new', 'retried')) RETURNING *
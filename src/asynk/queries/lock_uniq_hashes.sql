@@ -0,0 +1 @@
+SELECT pg_advisory_xact_lock(hashtextextended(uniq_hash, 0)) FROM UNNEST($1::text[]) AS uniq_hash
@@ -0,0 +1 @@
+UPDATE "fang_tasks" SET "state" = 'new' , "retries" = 0 , "scheduled_at" = $1 , "updated_at" = $1 WHERE task_type = $2 AND state IN ('failed', 'dead') RETURNING *
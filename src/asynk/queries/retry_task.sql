@@ -1 +1 @@
-UPDATE "fang_tasks" SET "state" = 'retried' , "error_message" = $1, "retries" = $2, scheduled_at = $3, "updated_at" = $4 WHERE id = $5 RETURNING *
+UPDATE "fang_tasks" SET "state" = 'retried' , "error_message" = $1, "error_details" = $2, "retries" = $3, scheduled_at = $4, "updated_at" = $5 WHERE id = $6 RETURNING *
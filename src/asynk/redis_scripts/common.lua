@@ -0,0 +1,81 @@
+-- Every task is stored in the `<prefix>:task:<id>` hash, ids of all tasks are stored in the `<prefix>:tasks` sorted set
+-- scored by `scheduled_at` and in the `<prefix>:type:<task_type>` set of their task type.
+-- Tasks that wait for execution are stored in `<prefix>:pending:<task_type>:<priority>` sorted sets
+-- scored by `scheduled_at`, their priorities are stored in the `<prefix>:priorities:<task_type>` sorted set.
+-- Tasks in progress are stored in the `<prefix>:in_progress` sorted set scored by their last heartbeat,
+-- failed and dead tasks are stored in the `<prefix>:failed` and `<prefix>:dead` sets.
+-- The `<prefix>:uniq:<uniq_hash>` key stores the id of the pending task with the hash.
+-- Timestamps are stored as microseconds since the epoch.
+--
+-- Scripts access only the keys passed in KEYS: the keys shared by all tasks come first,
+-- followed by the keys of every task the script changes in the order of `task_keys`.
+local tasks_key, in_progress_key, failed_key, dead_key = KEYS[1], KEYS[2], KEYS[3], KEYS[4]
+
+-- the keys of the n-th task passed to the script, the uniq key isn't used if the task isn't unique
+local function task_keys(n)
+  local offset = 4 + (n - 1) * 5
+
+  return {
+    task = KEYS[offset + 1],
+    task_type = KEYS[offset + 2],
+    pending = KEYS[offset + 3],
+    priorities = KEYS[offset + 4],
+    uniq = KEYS[offset + 5],
+  }
+end
+
+local function is_pending(state)
+  return state == 'new' or state == 'retried'
+end
+
+local function index(id, keys)
+  local task = redis.call('HMGET', keys.task, 'state', 'priority', 'uniq_hash', 'scheduled_at', 'heartbeat_at', 'updated_at')
+  local state, priority, uniq_hash, scheduled_at = task[1], task[2], task[3], task[4]
+
+  redis.call('ZADD', tasks_key, scheduled_at, id)
+  redis.call('SADD', keys.task_type, id)
+
+  if is_pending(state) then
+    redis.call('ZADD', keys.pending, scheduled_at, id)
+    redis.call('ZADD', keys.priorities, -tonumber(priority), priority)
+
+    if uniq_hash and redis.call('EXISTS', keys.uniq) == 0 then
+      redis.call('SET', keys.uniq, id)
+    end
+  elseif state == 'in_progress' then
+    redis.call('ZADD', in_progress_key, task[5] or task[6], id)
+  elseif state == 'failed' then
+    redis.call('SADD', failed_key, id)
+  elseif state == 'dead' then
+    redis.call('SADD', dead_key, id)
+  end
+end
+
+local function unindex(id, keys)
+  local task = redis.call('HMGET', keys.task, 'state', 'priority', 'uniq_hash')
+  local state, priority, uniq_hash = task[1], task[2], task[3]
+
+  if is_pending(state) then
+    redis.call('ZREM', keys.pending, id)
+
+    if redis.call('ZCARD', keys.pending) == 0 then
+      redis.call('ZREM', keys.priorities, priority)
+    end
+  end
+
+  redis.call('ZREM', in_progress_key, id)
+  redis.call('SREM', failed_key, id)
+  redis.call('SREM', dead_key, id)
+
+  if uniq_hash and redis.call('GET', keys.uniq) == id then
+    redis.call('DEL', keys.uniq)
+  end
+end
+
+local function remove(id, keys)
+  unindex(id, keys)
+  redis.call('DEL', keys.task)
+  redis.call('ZREM', tasks_key, id)
+  redis.call('SREM', keys.task_type, id)
+end
+
@@ -0,0 +1,21 @@
+-- KEYS: shared keys, keys of the candidate tasks
+-- ARGV: now, ids of the candidate tasks in the order they are claimed
+-- Candidates are selected before the script runs, so they can be claimed by another worker in the meantime.
+-- Only the candidates that are still pending and scheduled before `now` are claimed
+local now = ARGV[1]
+local ids = {}
+
+for n = 2, #ARGV do
+  local id = ARGV[n]
+  local keys = task_keys(n - 1)
+  local scheduled_at = redis.call('ZSCORE', keys.pending, id)
+
+  if scheduled_at and tonumber(scheduled_at) <= tonumber(now) then
+    unindex(id, keys)
+    redis.call('HSET', keys.task, 'state', 'in_progress', 'heartbeat_at', now, 'updated_at', now)
+    index(id, keys)
+    table.insert(ids, id)
+  end
+end
+
+return ids
@@ -0,0 +1,17 @@
+-- KEYS: shared keys, keys of the task
+-- ARGV: id, uniq_hash (empty if the task is not unique), field/value pairs of the task
+local id, uniq_hash = ARGV[1], ARGV[2]
+local keys = task_keys(1)
+
+if uniq_hash ~= '' then
+  local existing_id = redis.call('GET', keys.uniq)
+
+  if existing_id then
+    return existing_id
+  end
+end
+
+redis.call('HSET', keys.task, unpack(ARGV, 3))
+index(id, keys)
+
+return id
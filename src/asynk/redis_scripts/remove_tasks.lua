@@ -0,0 +1,31 @@
+-- KEYS: shared keys, keys of the tasks
+-- ARGV: field, value, ids of the tasks
+-- Removes the tasks whose `field` equals `value`, tasks scheduled after `value` if `field` is `scheduled_at`,
+-- dead tasks updated before `value` if `field` is `dead` or all tasks if `field` is `all`
+local field, value = ARGV[1], ARGV[2]
+local removed = 0
+
+for n = 3, #ARGV do
+  local id = ARGV[n]
+  local keys = task_keys(n - 2)
+  local matches
+
+  if field == 'all' then
+    matches = redis.call('EXISTS', keys.task) == 1
+  elseif field == 'scheduled_at' then
+    local scheduled_at = redis.call('HGET', keys.task, 'scheduled_at')
+    matches = scheduled_at and tonumber(scheduled_at) > tonumber(value)
+  elseif field == 'dead' then
+    local task = redis.call('HMGET', keys.task, 'state', 'updated_at')
+    matches = task[1] == 'dead' and tonumber(task[2]) < tonumber(value)
+  else
+    matches = redis.call('HGET', keys.task, field) == value
+  end
+
+  if matches then
+    remove(id, keys)
+    removed = removed + 1
+  end
+end
+
+return removed
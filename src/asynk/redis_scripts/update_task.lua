@@ -0,0 +1,17 @@
+-- KEYS: shared keys, keys of the task
+-- ARGV: id, expected state and updated_at (empty if any), field/value pairs to update
+local id, expected_state, expected_updated_at = ARGV[1], ARGV[2], ARGV[3]
+local keys = task_keys(1)
+local state, updated_at = unpack(redis.call('HMGET', keys.task, 'state', 'updated_at'))
+
+if not state
+  or (expected_state ~= '' and state ~= expected_state)
+  or (expected_updated_at ~= '' and updated_at ~= expected_updated_at) then
+  return 0
+end
+
+unindex(id, keys)
+redis.call('HSET', keys.task, unpack(ARGV, 4))
+index(id, keys)
+
+return 1
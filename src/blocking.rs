@@ -1,14 +1,33 @@
 mod error;
-pub mod fang_task_state;
+pub mod in_memory_queue;
+#[cfg(feature = "mysql")]
+pub mod mysql_queue;
+#[cfg(feature = "mysql")]
+pub mod mysql_schema;
+#[cfg(feature = "blocking")]
 pub mod queue;
+pub mod queueable;
 pub mod runnable;
+#[cfg(feature = "blocking")]
 pub mod schema;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_queue;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_schema;
 pub mod worker;
 pub mod worker_pool;
 
-pub use fang_task_state::FangTaskState;
+pub use crate::task::FangTaskState;
+pub use in_memory_queue::InMemoryQueue;
+#[cfg(feature = "mysql")]
+pub use mysql_queue::MysqlQueue;
+#[cfg(feature = "blocking")]
 pub use queue::*;
+pub use queueable::*;
 pub use runnable::Runnable;
+#[cfg(feature = "blocking")]
 pub use schema::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite_queue::SqliteQueue;
 pub use worker::*;
 pub use worker_pool::*;
@@ -1,4 +1,4 @@
-use crate::blocking::queue::QueueError;
+use crate::blocking::queueable::QueueError;
 use crate::FangError;
 use diesel::r2d2::PoolError;
 use diesel::result::Error as DieselError;
@@ -6,15 +6,21 @@ use std::io::Error as IoError;
 
 impl From<IoError> for FangError {
     fn from(error: IoError) -> Self {
-        let description = format!("{:?}", error);
-        FangError { description }
+        FangError::retryable(error.to_string()).with_source(error)
     }
 }
 
 impl From<QueueError> for FangError {
     fn from(error: QueueError) -> Self {
-        let description = format!("{:?}", error);
-        FangError { description }
+        let description = error.to_string();
+
+        match error {
+            // the record the task works with was deleted
+            QueueError::DieselError(DieselError::NotFound) => {
+                FangError::permanent(description).with_source(error)
+            }
+            _ => FangError::retryable(description).with_source(error),
+        }
     }
 }
 
@@ -0,0 +1,834 @@
+use crate::in_memory_store::InMemoryStore;
+use crate::new_tasks;
+use crate::new_tasks::NewTasks;
+use crate::queueable;
+use crate::queueable::QueueError;
+use crate::queueable::Queueable;
+use crate::queueable::Task;
+use crate::queueable::TaskAttempt;
+use crate::runnable::Runnable;
+use crate::task::FangTaskState;
+use crate::FangError;
+use crate::EXPIRED_HEARTBEAT_ERROR;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+use diesel::result::Error as DieselError;
+use uuid::Uuid;
+
+/// A queue that keeps tasks in memory.
+///
+/// It implements [`Queueable`] with the same semantics as [`Queue`],
+/// so tasks can be tested and a `Worker` or a `WorkerPool` can be run without a database.
+/// Clones of the queue share the same tasks, they are lost once the last clone is dropped.
+///
+///    ```rust
+///         let queue = InMemoryQueue::default();
+///
+///         queue.insert_task(&MyTask { number: 1 })?;
+///
+///         let mut worker_pool = WorkerPool::<InMemoryQueue>::builder()
+///             .queue(queue.clone())
+///             .number_of_workers(2_u32)
+///             .build();
+///     ```
+///
+/// The queue doesn't send notifications, idle workers poll it according to their `SleepParams`.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryQueue {
+    store: InMemoryStore,
+    record_reaped_attempts: bool,
+}
+
+impl InMemoryQueue {
+    /// Set if `reap_expired_tasks` records the reaped executions of tasks
+    /// as attempts of the `REAPER_WORKER_ID`, it doesn't by default
+    pub fn record_reaped_attempts(mut self, record_reaped_attempts: bool) -> Self {
+        self.record_reaped_attempts = record_reaped_attempts;
+        self
+    }
+
+    /// Return all tasks that are stored in the queue
+    pub fn tasks(&self) -> Vec<Task> {
+        self.store.tasks()
+    }
+
+    fn insert(
+        &self,
+        tasks: &[&dyn Runnable],
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<Vec<Task>, QueueError> {
+        let mut new_tasks = NewTasks::default();
+
+        for task in tasks {
+            let metadata = serde_json::to_value(task).unwrap();
+
+            new_tasks.add(
+                metadata,
+                task.task_type(),
+                task.uniq(),
+                task.priority(),
+                scheduled_at,
+            );
+        }
+
+        Ok(self.store.insert(new_tasks))
+    }
+
+    fn update(&self, id: Uuid, update: impl FnOnce(&mut Task)) -> Result<Task, QueueError> {
+        self.store
+            .update(id, update)
+            .ok_or(QueueError::DieselError(DieselError::NotFound))
+    }
+}
+
+impl Queueable for InMemoryQueue {
+    fn fetch_and_touch_task(&self, task_type: String) -> Result<Option<Task>, QueueError> {
+        let mut tasks = self.fetch_and_touch_tasks(task_type, 1)?;
+
+        Ok(tasks.pop())
+    }
+
+    fn fetch_and_touch_tasks(
+        &self,
+        task_type: String,
+        limit: u32,
+    ) -> Result<Vec<Task>, QueueError> {
+        Ok(self.store.fetch_and_touch(&task_type, limit))
+    }
+
+    fn insert_task(&self, params: &dyn Runnable) -> Result<Task, QueueError> {
+        let mut tasks = self.insert(&[params], Utc::now())?;
+
+        Ok(tasks.pop().unwrap())
+    }
+
+    fn insert_tasks(&self, tasks: &[&dyn Runnable]) -> Result<Vec<Task>, QueueError> {
+        self.insert(tasks, Utc::now())
+    }
+
+    fn remove_all_tasks(&self) -> Result<usize, QueueError> {
+        Ok(self.store.remove(|_| true))
+    }
+
+    fn remove_all_scheduled_tasks(&self) -> Result<usize, QueueError> {
+        let now = Utc::now();
+
+        Ok(self.store.remove(|task| task.scheduled_at > now))
+    }
+
+    fn remove_tasks_of_type(&self, task_type: &str) -> Result<usize, QueueError> {
+        Ok(self.store.remove(|task| task.task_type == task_type))
+    }
+
+    fn remove_task(&self, id: Uuid) -> Result<usize, QueueError> {
+        Ok(self.store.remove(|task| task.id == id))
+    }
+
+    fn remove_task_by_metadata(&self, task: &dyn Runnable) -> Result<usize, QueueError> {
+        if task.uniq() {
+            let metadata = serde_json::to_value(task).unwrap();
+            let uniq_hash = new_tasks::calculate_hash(metadata.to_string());
+
+            Ok(self
+                .store
+                .remove(|task| task.uniq_hash.as_ref() == Some(&uniq_hash)))
+        } else {
+            Err(QueueError::TaskNotUniqError)
+        }
+    }
+
+    fn find_task_by_id(&self, id: Uuid) -> Option<Task> {
+        self.store.find(id)
+    }
+
+    fn update_task_state(&self, task: &Task, state: FangTaskState) -> Result<Task, QueueError> {
+        self.update(task.id, |task| {
+            task.state = state;
+            task.updated_at = Utc::now();
+        })
+    }
+
+    fn fail_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError> {
+        self.update(task.id, |task| {
+            InMemoryStore::fail(
+                task,
+                FangTaskState::Failed,
+                &error.description,
+                error.details.as_ref(),
+            )
+        })
+    }
+
+    fn dead_letter_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError> {
+        self.update(task.id, |task| {
+            InMemoryStore::fail(
+                task,
+                FangTaskState::Dead,
+                &error.description,
+                error.details.as_ref(),
+            )
+        })
+    }
+
+    fn schedule_task(&self, task: &dyn Runnable) -> Result<Task, QueueError> {
+        let scheduled_at = queueable::calculate_scheduled_at(task)?;
+        let mut tasks = self.insert(&[task], scheduled_at)?;
+
+        Ok(tasks.pop().unwrap())
+    }
+
+    fn schedule_retry(
+        &self,
+        task: &Task,
+        backoff_in_seconds: u32,
+        error: &FangError,
+    ) -> Result<Task, QueueError> {
+        self.update(task.id, |stored_task| {
+            InMemoryStore::retry(
+                stored_task,
+                task.retries,
+                backoff_in_seconds,
+                &error.description,
+                error.details.as_ref(),
+            )
+        })
+    }
+
+    fn heartbeat_task(&self, task: &Task) -> Result<usize, QueueError> {
+        Ok(self.store.heartbeat(task))
+    }
+
+    fn reap_expired_tasks(
+        &self,
+        heartbeat_timeout: std::time::Duration,
+    ) -> Result<usize, QueueError> {
+        let heartbeat_timeout =
+            Duration::from_std(heartbeat_timeout).map_err(|_| QueueError::TimeError)?;
+        let expired_at = Utc::now() - heartbeat_timeout;
+
+        Ok(self.store.reap(
+            expired_at,
+            EXPIRED_HEARTBEAT_ERROR,
+            self.record_reaped_attempts,
+            |task| match serde_json::from_value::<Box<dyn Runnable>>(task.metadata.clone()) {
+                Ok(runnable) if task.retries < runnable.max_retries() => {
+                    Some(Some(runnable.backoff(task.retries as u32)))
+                }
+                Ok(_) => Some(None),
+                Err(_) => None,
+            },
+        ))
+    }
+
+    fn retry_failed_task(&self, id: Uuid) -> Result<Task, QueueError> {
+        self.store
+            .retry_failed(|task| task.id == id)
+            .pop()
+            .ok_or(QueueError::DieselError(DieselError::NotFound))
+    }
+
+    fn retry_failed_tasks_of_type(&self, task_type: &str) -> Result<usize, QueueError> {
+        Ok(self
+            .store
+            .retry_failed(|task| task.task_type == task_type)
+            .len())
+    }
+
+    fn discard_dead_tasks(&self, older_than: std::time::Duration) -> Result<usize, QueueError> {
+        let older_than = Duration::from_std(older_than).map_err(|_| QueueError::TimeError)?;
+        let updated_before = Utc::now() - older_than;
+
+        Ok(self
+            .store
+            .remove(|task| task.state == FangTaskState::Dead && task.updated_at < updated_before))
+    }
+
+    fn record_task_attempt(&self, attempt: &TaskAttempt) -> Result<(), QueueError> {
+        if self.store.record_attempt(attempt) {
+            Ok(())
+        } else {
+            Err(QueueError::DieselError(DieselError::NotFound))
+        }
+    }
+
+    fn find_task_attempts(&self, task_id: Uuid) -> Result<Vec<TaskAttempt>, QueueError> {
+        Ok(self.store.find_attempts(task_id))
+    }
+}
+
+#[cfg(test)]
+mod in_memory_queue_tests {
+    use super::InMemoryQueue;
+    use crate::chrono::SubsecRound;
+    use crate::queueable::QueueError;
+    use crate::queueable::Queueable;
+    use crate::queueable::Task;
+    use crate::runnable::Runnable;
+    use crate::runnable::COMMON_TYPE;
+    use crate::task::FangTaskState;
+    use crate::typetag;
+    use crate::worker::Worker;
+    use crate::FangError;
+    use crate::RetentionMode;
+    use crate::Scheduled;
+    use crate::TaskContext;
+    use crate::EXPIRED_HEARTBEAT_ERROR;
+    use crate::REAPER_WORKER_ID;
+    use chrono::DateTime;
+    use chrono::Duration;
+    use chrono::Utc;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct MemoryTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for MemoryTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct MemoryUrgentTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for MemoryUrgentTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn priority(&self) -> i16 {
+            10
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct MemoryUniqTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for MemoryUniqTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn uniq(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct MemoryScheduledTask {
+        pub number: u16,
+        pub datetime: String,
+    }
+
+    #[typetag::serde]
+    impl Runnable for MemoryScheduledTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn cron(&self) -> Option<Scheduled> {
+            let datetime = self.datetime.parse::<DateTime<Utc>>().ok()?;
+            Some(Scheduled::ScheduleOnce(datetime))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct MemoryFailingTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for MemoryFailingTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Err(FangError::retryable("Failed"))
+        }
+
+        fn task_type(&self) -> String {
+            "in_memory_failing".to_string()
+        }
+
+        fn max_retries(&self) -> i32 {
+            1
+        }
+    }
+
+    #[test]
+    fn insert_task_creates_new_task() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+
+        assert_eq!(Some(1), task.metadata["number"].as_u64());
+        assert_eq!(Some("MemoryTask"), task.metadata["type"].as_str());
+        assert_eq!(FangTaskState::New, task.state);
+        assert_eq!(COMMON_TYPE, task.task_type);
+        assert_eq!(None, task.uniq_hash);
+        assert_eq!(vec![task.clone()], queue.tasks());
+        assert_eq!(Some(task.clone()), queue.find_task_by_id(task.id));
+    }
+
+    #[test]
+    fn insert_task_does_not_duplicate_uniq_tasks() {
+        let queue = InMemoryQueue::default();
+
+        let task1 = queue.insert_task(&MemoryUniqTask { number: 1 }).unwrap();
+        let task2 = queue.insert_task(&MemoryUniqTask { number: 1 }).unwrap();
+        let tasks = queue
+            .insert_tasks(&[
+                &MemoryUniqTask { number: 1 },
+                &MemoryUniqTask { number: 2 },
+                &MemoryUniqTask { number: 2 },
+            ])
+            .unwrap();
+
+        assert!(task1.uniq_hash.is_some());
+        assert_eq!(task1.id, task2.id);
+        assert_eq!(task1.id, tasks[0].id);
+        assert_eq!(tasks[1].id, tasks[2].id);
+        assert_eq!(2, queue.tasks().len());
+
+        queue
+            .update_task_state(&task1, FangTaskState::Finished)
+            .unwrap();
+
+        let task3 = queue.insert_task(&MemoryUniqTask { number: 1 }).unwrap();
+
+        assert_ne!(task1.id, task3.id);
+    }
+
+    #[test]
+    fn fetch_and_touch_respects_type_and_priority() {
+        let queue = InMemoryQueue::default();
+
+        let task1 = queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+        let task2 = queue.insert_task(&MemoryUrgentTask { number: 2 }).unwrap();
+        queue.insert_task(&MemoryFailingTask { number: 3 }).unwrap();
+
+        let fetched_task = queue
+            .fetch_and_touch_task(COMMON_TYPE.to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(task2.id, fetched_task.id);
+        assert_eq!(FangTaskState::InProgress, fetched_task.state);
+        assert!(fetched_task.heartbeat_at.is_some());
+
+        let tasks = queue
+            .fetch_and_touch_tasks(COMMON_TYPE.to_string(), 2)
+            .unwrap();
+
+        assert_eq!(1, tasks.len());
+        assert_eq!(task1.id, tasks[0].id);
+        assert_eq!(
+            None,
+            queue.fetch_and_touch_task(COMMON_TYPE.to_string()).unwrap()
+        );
+    }
+
+    #[test]
+    fn schedule_task_test() {
+        let queue = InMemoryQueue::default();
+
+        let datetime = (Utc::now() + Duration::seconds(7)).round_subsecs(0);
+
+        let task = queue
+            .schedule_task(&MemoryScheduledTask {
+                number: 1,
+                datetime: datetime.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(datetime, task.scheduled_at);
+        assert_eq!(
+            None,
+            queue.fetch_and_touch_task(COMMON_TYPE.to_string()).unwrap()
+        );
+        assert_eq!(1, queue.remove_all_scheduled_tasks().unwrap());
+        assert!(queue.tasks().is_empty());
+    }
+
+    #[test]
+    fn schedule_retry_test() {
+        let queue = InMemoryQueue::default();
+
+        queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+
+        let task = queue
+            .fetch_and_touch_task(COMMON_TYPE.to_string())
+            .unwrap()
+            .unwrap();
+
+        let retried_task = queue
+            .schedule_retry(&task, 60, &FangError::retryable("Failed"))
+            .unwrap();
+
+        assert_eq!(FangTaskState::Retried, retried_task.state);
+        assert_eq!(1, retried_task.retries);
+        assert_eq!(Some("Failed".to_string()), retried_task.error_message);
+        assert!(retried_task.scheduled_at > Utc::now() + Duration::seconds(50));
+        assert_eq!(
+            None,
+            queue.fetch_and_touch_task(COMMON_TYPE.to_string()).unwrap()
+        );
+
+        let failed_task = queue
+            .fail_task(&retried_task, &FangError::retryable("Some error"))
+            .unwrap();
+
+        assert_eq!(FangTaskState::Failed, failed_task.state);
+        assert_eq!(Some("Some error".to_string()), failed_task.error_message);
+    }
+
+    #[test]
+    fn remove_tasks_test() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+        queue.insert_task(&MemoryUniqTask { number: 1 }).unwrap();
+        queue.insert_task(&MemoryFailingTask { number: 1 }).unwrap();
+
+        assert_eq!(1, queue.remove_task(task.id).unwrap());
+        assert_eq!(0, queue.remove_task(task.id).unwrap());
+        assert!(matches!(
+            queue.remove_task_by_metadata(&MemoryTask { number: 1 }),
+            Err(QueueError::TaskNotUniqError)
+        ));
+        assert_eq!(
+            1,
+            queue
+                .remove_task_by_metadata(&MemoryUniqTask { number: 1 })
+                .unwrap()
+        );
+        assert_eq!(1, queue.remove_tasks_of_type("in_memory_failing").unwrap());
+        assert!(queue.tasks().is_empty());
+        assert!(queue.update_task_state(&task, FangTaskState::New).is_err());
+    }
+
+    #[test]
+    fn reap_expired_tasks_test() {
+        let queue = InMemoryQueue::default().record_reaped_attempts(true);
+
+        queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+
+        let task = queue
+            .fetch_and_touch_task(COMMON_TYPE.to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(1, queue.heartbeat_task(&task).unwrap());
+        assert_eq!(
+            0,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_secs(60))
+                .unwrap()
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        assert_eq!(
+            1,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_millis(1))
+                .unwrap()
+        );
+
+        let reaped_task = queue.find_task_by_id(task.id).unwrap();
+
+        assert_eq!(FangTaskState::Retried, reaped_task.state);
+        assert_eq!(1, reaped_task.retries);
+        assert_eq!(
+            Some(EXPIRED_HEARTBEAT_ERROR.to_string()),
+            reaped_task.error_message
+        );
+        assert_eq!(0, queue.heartbeat_task(&reaped_task).unwrap());
+
+        let attempts = queue.find_task_attempts(task.id).unwrap();
+
+        assert_eq!(1, attempts.len());
+        assert_eq!(1, attempts[0].attempt);
+        assert_eq!(REAPER_WORKER_ID, attempts[0].worker_id);
+        assert_eq!(FangTaskState::Retried, attempts[0].outcome);
+        assert_eq!(task.updated_at, attempts[0].started_at);
+
+        // the task is executed again, the heartbeats of its previous execution are ignored
+        let restarted_task = queue
+            .update_task_state(&reaped_task, FangTaskState::InProgress)
+            .unwrap();
+
+        assert_eq!(0, queue.heartbeat_task(&task).unwrap());
+        assert_eq!(1, queue.heartbeat_task(&restarted_task).unwrap());
+    }
+
+    #[test]
+    fn worker_discards_result_of_reaped_task() {
+        let queue = InMemoryQueue::default();
+
+        queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+
+        let task = queue
+            .fetch_and_touch_task(COMMON_TYPE.to_string())
+            .unwrap()
+            .unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        queue
+            .reap_expired_tasks(std::time::Duration::from_millis(1))
+            .unwrap();
+
+        let worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .retention_mode(RetentionMode::KeepAll)
+            .build();
+
+        worker.run(task.clone());
+
+        let found_task = queue.find_task_by_id(task.id).unwrap();
+
+        assert_eq!(FangTaskState::Retried, found_task.state);
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct MemoryInvalidTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for MemoryInvalidTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Err(FangError::permanent(format!("{} is invalid", self.number)))
+        }
+
+        fn task_type(&self) -> String {
+            "in_memory_invalid".to_string()
+        }
+    }
+
+    #[test]
+    fn worker_executes_tasks() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+        let failing_task = queue.insert_task(&MemoryFailingTask { number: 1 }).unwrap();
+
+        let mut worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .retention_mode(RetentionMode::KeepAll)
+            .build();
+
+        let mut failing_worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .task_type("in_memory_failing")
+            .retention_mode(RetentionMode::KeepAll)
+            .build();
+
+        worker.run_tasks_until_none().unwrap();
+        failing_worker.run_tasks_until_none().unwrap();
+
+        let task = queue.find_task_by_id(task.id).unwrap();
+        let failing_task = queue.find_task_by_id(failing_task.id).unwrap();
+
+        assert_eq!(FangTaskState::Finished, task.state);
+        assert_eq!(FangTaskState::Retried, failing_task.state);
+        assert_eq!(1, failing_task.retries);
+        assert_eq!(Some("Failed".to_string()), failing_task.error_message);
+    }
+
+    #[test]
+    fn worker_does_not_retry_permanent_errors() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue.insert_task(&MemoryInvalidTask { number: 1 }).unwrap();
+
+        let mut worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .task_type("in_memory_invalid")
+            .retention_mode(RetentionMode::KeepAll)
+            .build();
+
+        worker.run_tasks_until_none().unwrap();
+
+        let task = queue.find_task_by_id(task.id).unwrap();
+
+        assert_eq!(FangTaskState::Failed, task.state);
+        assert_eq!(0, task.retries);
+        assert_eq!(Some("1 is invalid".to_string()), task.error_message);
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct MemoryExhaustedTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for MemoryExhaustedTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Err(FangError::retryable("Failed"))
+        }
+
+        fn task_type(&self) -> String {
+            "in_memory_exhausted".to_string()
+        }
+
+        fn max_retries(&self) -> i32 {
+            0
+        }
+    }
+
+    #[test]
+    fn dead_tasks_are_kept_until_retried_or_discarded() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue
+            .insert_task(&MemoryExhaustedTask { number: 1 })
+            .unwrap();
+
+        let mut worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .task_type("in_memory_exhausted")
+            .retention_mode(RetentionMode::RemoveAll)
+            .build();
+
+        worker.run_tasks_until_none().unwrap();
+
+        let dead_task = queue.find_task_by_id(task.id).unwrap();
+        assert_eq!(FangTaskState::Dead, dead_task.state);
+
+        let retried_task = queue.retry_failed_task(task.id).unwrap();
+        assert_eq!(FangTaskState::New, retried_task.state);
+        assert!(queue.retry_failed_task(task.id).is_err());
+        assert_eq!(
+            0,
+            queue.discard_dead_tasks(std::time::Duration::ZERO).unwrap()
+        );
+
+        worker.run_tasks_until_none().unwrap();
+
+        assert_eq!(
+            0,
+            queue
+                .discard_dead_tasks(std::time::Duration::from_secs(60))
+                .unwrap()
+        );
+        assert_eq!(
+            1,
+            queue
+                .retry_failed_tasks_of_type("in_memory_exhausted")
+                .unwrap()
+        );
+
+        worker.run_tasks_until_none().unwrap();
+
+        assert_eq!(
+            1,
+            queue.discard_dead_tasks(std::time::Duration::ZERO).unwrap()
+        );
+        assert!(queue.tasks().is_empty());
+    }
+
+    #[test]
+    fn worker_keeps_permanently_failed_tasks_as_dead_with_remove_all() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue.insert_task(&MemoryInvalidTask { number: 1 }).unwrap();
+
+        let mut worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .task_type("in_memory_invalid")
+            .retention_mode(RetentionMode::RemoveAll)
+            .build();
+
+        worker.run_tasks_until_none().unwrap();
+
+        let task = queue.find_task_by_id(task.id).unwrap();
+
+        assert_eq!(FangTaskState::Dead, task.state);
+        assert_eq!(0, task.retries);
+        assert_eq!(Some("1 is invalid".to_string()), task.error_message);
+    }
+
+    #[test]
+    fn worker_records_attempts_of_tasks() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue.insert_task(&MemoryFailingTask { number: 1 }).unwrap();
+        let finished_task = queue.insert_task(&MemoryTask { number: 2 }).unwrap();
+
+        let worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .worker_id("worker_test")
+            .record_attempts(true)
+            .build();
+
+        let start = |task: &Task| {
+            queue
+                .update_task_state(task, FangTaskState::InProgress)
+                .unwrap()
+        };
+
+        worker.run(start(&task));
+        worker.run(start(&queue.find_task_by_id(task.id).unwrap()));
+        worker.run(start(&finished_task));
+
+        let attempts = queue.find_task_attempts(task.id).unwrap();
+
+        assert_eq!(2, attempts.len());
+        assert_eq!(1, attempts[0].attempt);
+        assert_eq!("worker_test", attempts[0].worker_id);
+        assert_eq!(FangTaskState::Retried, attempts[0].outcome);
+        assert_eq!(Some("Failed".to_string()), attempts[0].error_message);
+        assert_eq!(2, attempts[1].attempt);
+        assert_eq!(FangTaskState::Dead, attempts[1].outcome);
+
+        // the attempt of a task that is removed by the retention mode isn't recorded
+        assert!(queue
+            .find_task_attempts(finished_task.id)
+            .unwrap()
+            .is_empty());
+
+        queue.remove_task(task.id).unwrap();
+
+        assert!(queue.find_task_attempts(task.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn worker_fails_tasks_that_cannot_be_deserialized() {
+        let queue = InMemoryQueue::default();
+
+        let task = queue.insert_task(&MemoryTask { number: 1 }).unwrap();
+        queue.store.update(task.id, |task| {
+            task.metadata = serde_json::json!({"type": "RemovedTask", "number": 1})
+        });
+
+        let mut worker = Worker::<InMemoryQueue>::builder()
+            .queue(queue.clone())
+            .retention_mode(RetentionMode::RemoveAll)
+            .record_attempts(true)
+            .build();
+
+        worker.run_tasks_until_none().unwrap();
+
+        let task = queue.find_task_by_id(task.id).unwrap();
+
+        assert_eq!(FangTaskState::Failed, task.state);
+        assert_eq!(0, task.retries);
+        assert!(task
+            .error_message
+            .unwrap()
+            .starts_with("Failed to deserialize the task"));
+
+        let attempts = queue.find_task_attempts(task.id).unwrap();
+
+        assert_eq!(1, attempts.len());
+        assert_eq!(FangTaskState::Failed, attempts[0].outcome);
+    }
+}
@@ -0,0 +1,17 @@
+diesel::table! {
+    fang_tasks (id) {
+        id -> Char,
+        metadata -> Json,
+        error_message -> Nullable<Text>,
+        state -> Varchar,
+        task_type -> Varchar,
+        uniq_hash -> Nullable<Char>,
+        retries -> Integer,
+        scheduled_at -> Datetime,
+        created_at -> Datetime,
+        updated_at -> Datetime,
+        heartbeat_at -> Nullable<Datetime>,
+        priority -> SmallInt,
+        error_details -> Nullable<Json>,
+    }
+}
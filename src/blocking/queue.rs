@@ -1,26 +1,49 @@
-use crate::fang_task_state::FangTaskState;
+use crate::listen_query;
+use crate::migrations;
+use crate::new_tasks;
+use crate::new_tasks::NewTasks;
+use crate::notification_channel;
+use crate::queueable;
 use crate::runnable::Runnable;
+use crate::schema::fang_task_attempts;
 use crate::schema::fang_tasks;
-use crate::CronError;
-use crate::Scheduled::*;
+use crate::search_path;
+use crate::task::FangTaskState;
+use crate::FangError;
+use crate::EXPIRED_HEARTBEAT_ERROR;
 use chrono::DateTime;
 use chrono::Duration;
 use chrono::Utc;
-use cron::Schedule;
+use diesel::connection::SimpleConnection;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::r2d2;
 use diesel::r2d2::ConnectionManager;
-use diesel::r2d2::PoolError;
+use diesel::r2d2::CustomizeConnection;
 use diesel::r2d2::PooledConnection;
-use diesel::result::Error as DieselError;
-use sha2::Digest;
-use sha2::Sha256;
-use std::str::FromStr;
-use thiserror::Error;
+use diesel::sql_types::Array;
+use diesel::sql_types::BigInt;
+use diesel::sql_types::Bool;
+use diesel::sql_types::Jsonb;
+use diesel::sql_types::Nullable;
+use diesel::sql_types::SmallInt;
+use diesel::sql_types::Text;
+use diesel::sql_types::Timestamptz;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::thread;
 use typed_builder::TypedBuilder;
 use uuid::Uuid;
 
+pub use crate::queueable::QueueError;
+pub use crate::queueable::Queueable;
+pub use crate::queueable::TaskListener;
+pub use crate::task::Task;
+pub use crate::task::TaskAttempt;
+
 #[cfg(test)]
 use dotenv::dotenv;
 #[cfg(test)]
@@ -28,29 +51,44 @@ use std::env;
 
 pub type PoolConnection = PooledConnection<ConnectionManager<PgConnection>>;
 
-#[derive(Queryable, Identifiable, Debug, Eq, PartialEq, Clone, TypedBuilder)]
-#[diesel(table_name = fang_tasks)]
-pub struct Task {
-    #[builder(setter(into))]
-    pub id: Uuid,
-    #[builder(setter(into))]
-    pub metadata: serde_json::Value,
-    #[builder(setter(into))]
-    pub error_message: Option<String>,
-    #[builder(setter(into))]
-    pub state: FangTaskState,
-    #[builder(setter(into))]
-    pub task_type: String,
-    #[builder(setter(into))]
-    pub uniq_hash: Option<String>,
-    #[builder(setter(into))]
-    pub retries: i32,
-    #[builder(setter(into))]
-    pub scheduled_at: DateTime<Utc>,
-    #[builder(setter(into))]
-    pub created_at: DateTime<Utc>,
-    #[builder(setter(into))]
-    pub updated_at: DateTime<Utc>,
+/// Sets the `search_path` of every connection to the schema of a queue
+#[derive(Debug, Clone)]
+struct SchemaConnectionOptions {
+    schema: String,
+}
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for SchemaConnectionOptions {
+    fn on_acquire(&self, connection: &mut PgConnection) -> Result<(), r2d2::Error> {
+        connection
+            .batch_execute(&format!("SET search_path TO {}", search_path(&self.schema)))
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+const FETCH_AND_TOUCH_TASKS_QUERY: &str = "UPDATE fang_tasks SET state = 'in_progress', heartbeat_at = $2, updated_at = $2 \
+    WHERE id = ANY(ARRAY(SELECT id FROM fang_tasks WHERE task_type = $1 AND state IN ('new', 'retried') AND scheduled_at <= $2 \
+    ORDER BY priority DESC, scheduled_at ASC, created_at ASC LIMIT $3 FOR UPDATE SKIP LOCKED)) RETURNING *";
+
+const INSERT_TASKS_QUERY: &str = "INSERT INTO fang_tasks (id, metadata, task_type, uniq_hash, scheduled_at, priority) \
+    SELECT * FROM UNNEST($1::uuid[], $2::jsonb[], $3::varchar[], $4::text[], $5::timestamptz[], $6::int2[]) \
+    AS new_tasks (id, metadata, task_type, uniq_hash, scheduled_at, priority) \
+    WHERE new_tasks.uniq_hash IS NULL OR NOT EXISTS (SELECT 1 FROM fang_tasks \
+    WHERE fang_tasks.uniq_hash = new_tasks.uniq_hash AND fang_tasks.state IN ('new', 'retried')) RETURNING *";
+
+const LOCK_UNIQ_HASHES_QUERY: &str =
+    "SELECT pg_advisory_xact_lock(hashtextextended(uniq_hash, 0)) \
+    FROM UNNEST($1::text[]) AS uniq_hash";
+
+#[derive(QueryableByName)]
+struct MigrationVersion {
+    #[diesel(sql_type = Text)]
+    version: String,
+}
+
+#[derive(QueryableByName)]
+struct DieselMigrationsExist {
+    #[diesel(sql_type = Bool)]
+    exists: bool,
 }
 
 #[derive(Insertable, Debug, Eq, PartialEq, Clone, TypedBuilder)]
@@ -64,74 +102,8 @@ pub struct NewTask {
     uniq_hash: Option<String>,
     #[builder(setter(into))]
     scheduled_at: DateTime<Utc>,
-}
-
-#[derive(Debug, Error)]
-pub enum QueueError {
-    #[error(transparent)]
-    DieselError(#[from] DieselError),
-    #[error(transparent)]
-    PoolError(#[from] PoolError),
-    #[error(transparent)]
-    CronError(#[from] CronError),
-    #[error("Can not perform this operation if task is not uniq, please check its definition in impl Runnable")]
-    TaskNotUniqError,
-}
-
-impl From<cron::error::Error> for QueueError {
-    fn from(error: cron::error::Error) -> Self {
-        QueueError::CronError(CronError::LibraryError(error))
-    }
-}
-
-/// This trait defines operations for a synchronous queue.
-/// The trait can be implemented for different storage backends.
-/// For now, the trait is only implemented for PostgreSQL. More backends are planned to be implemented in the future.
-pub trait Queueable {
-    /// This method should retrieve one task of the `task_type` type. If `task_type` is `None` it will try to
-    /// fetch a task of the type `common`. After fetching it should update the state of the task to
-    /// `FangTaskState::InProgress`.
-    fn fetch_and_touch_task(&self, task_type: String) -> Result<Option<Task>, QueueError>;
-
-    /// Enqueue a task to the queue, The task will be executed as soon as possible by the worker of the same type
-    /// created by an `WorkerPool`.
-    fn insert_task(&self, params: &dyn Runnable) -> Result<Task, QueueError>;
-
-    /// The method will remove all tasks from the queue
-    fn remove_all_tasks(&self) -> Result<usize, QueueError>;
-
-    /// Remove all tasks that are scheduled in the future.
-    fn remove_all_scheduled_tasks(&self) -> Result<usize, QueueError>;
-
-    /// Removes all tasks that have the specified `task_type`.
-    fn remove_tasks_of_type(&self, task_type: &str) -> Result<usize, QueueError>;
-
-    /// Remove a task by its id.
-    fn remove_task(&self, id: Uuid) -> Result<usize, QueueError>;
-
-    /// To use this function task has to be uniq. uniq() has to return true.
-    /// If task is not uniq this function will not do anything.
-    /// Remove a task by its metadata (struct fields values)
-    fn remove_task_by_metadata(&self, task: &dyn Runnable) -> Result<usize, QueueError>;
-
-    fn find_task_by_id(&self, id: Uuid) -> Option<Task>;
-
-    /// Update the state field of the specified task
-    /// See the `FangTaskState` enum for possible states.
-    fn update_task_state(&self, task: &Task, state: FangTaskState) -> Result<Task, QueueError>;
-
-    /// Update the state of a task to `FangTaskState::Failed` and set an error_message.
-    fn fail_task(&self, task: &Task, error: &str) -> Result<Task, QueueError>;
-
-    /// Schedule a task.
-    fn schedule_task(&self, task: &dyn Runnable) -> Result<Task, QueueError>;
-
-    fn schedule_retry(
-        &self,
-        task: &Task,
-        backoff_in_seconds: u32,
-        error: &str,
-    ) -> Result<Task, QueueError>;
+    #[builder(setter(into))]
+    priority: i16,
 }
 
 /// An async queue that can be used to enqueue tasks.
@@ -155,10 +127,186 @@ pub trait Queueable {
 ///         let queue = Queue::builder().connection_pool(connection_pool(3)).build();
 ///     ```
 ///
+/// If `notifications` are enabled, the queue sends a PostgreSQL notification
+/// on the channel of the task type every time a task is enqueued or retried.
+/// Idle workers listen on this channel and wake up immediately instead of waiting
+/// for their `sleep_period` to pass. The workers of a `WorkerPool` share one connection to listen on,
+/// it's opened with `database_url` outside of the pool.
+///
 #[derive(Clone, TypedBuilder)]
 pub struct Queue {
     #[builder(setter(into))]
     pub connection_pool: r2d2::Pool<r2d2::ConnectionManager<PgConnection>>,
+    /// notifications controls if the queue notifies workers about new tasks with `NOTIFY`
+    /// and if workers listen for them
+    #[builder(default = false, setter(into))]
+    pub notifications: bool,
+    /// The URL of the database that workers open their listening connection with, it's required if `notifications` are enabled
+    #[builder(default, setter(into, strip_option))]
+    pub database_url: Option<String>,
+    /// The PostgreSQL schema of the `fang_tasks` table, the default schema of the connection is used if it's not set.
+    ///
+    /// Create the pool with [`Queue::schema_connection_pool`], its connections look up the tables in the schema,
+    /// so the pool shouldn't be shared with queues of other schemas
+    #[builder(default, setter(into, strip_option))]
+    pub schema: Option<String>,
+    /// record_reaped_attempts controls if `reap_expired_tasks` records the reaped executions of tasks
+    /// as attempts of the `REAPER_WORKER_ID`
+    #[builder(default = false, setter(into))]
+    pub record_reaped_attempts: bool,
+}
+
+/// How often the listener checks if notifications were received
+const NOTIFICATIONS_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+impl TaskListener {
+    /// Start listening on the `channel` with a connection returned by `connect`.
+    /// `connect` is called again to reconnect when the connection fails
+    pub fn listen<F>(connect: F, channel: String) -> Result<Self, QueueError>
+    where
+        F: Fn() -> Result<PgConnection, QueueError> + Send + 'static,
+    {
+        let connection = Self::connect(&connect, &channel)?;
+        let notifications = Arc::new(AtomicU64::new(0));
+        let counter = Arc::downgrade(&notifications);
+
+        thread::spawn(move || Self::receive_notifications(connection, connect, channel, counter));
+
+        Ok(TaskListener::new(notifications))
+    }
+
+    fn connect<F>(connect: &F, channel: &str) -> Result<PgConnection, QueueError>
+    where
+        F: Fn() -> Result<PgConnection, QueueError>,
+    {
+        let mut connection = connect()?;
+
+        diesel::sql_query(listen_query(channel)).execute(&mut connection)?;
+
+        Ok(connection)
+    }
+
+    fn receive_notifications<F>(
+        connection: PgConnection,
+        connect: F,
+        channel: String,
+        counter: Weak<AtomicU64>,
+    ) where
+        F: Fn() -> Result<PgConnection, QueueError>,
+    {
+        let mut connection = Some(connection);
+
+        loop {
+            thread::sleep(NOTIFICATIONS_CHECK_INTERVAL);
+
+            let notifications = match counter.upgrade() {
+                Some(notifications) => notifications,
+                None => break,
+            };
+
+            match connection.as_mut() {
+                Some(listening_connection) => {
+                    let mut received = false;
+                    let mut failed = false;
+
+                    for notification in listening_connection.notifications_iter() {
+                        match notification {
+                            Ok(_) => received = true,
+                            Err(error) => {
+                                log::error!(
+                                    "Failed to receive notifications about tasks {:?}",
+                                    error
+                                );
+                                failed = true;
+                                break;
+                            }
+                        }
+                    }
+
+                    if received {
+                        notifications.fetch_add(1, Ordering::SeqCst);
+                    }
+
+                    // the listener reconnects on the next check
+                    if failed {
+                        connection = None;
+                    }
+                }
+                None => match Self::connect(&connect, &channel) {
+                    Ok(new_connection) => {
+                        connection = Some(new_connection);
+
+                        // tasks that were enqueued while the listener was disconnected weren't notified
+                        notifications.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Err(error) => {
+                        log::error!("Failed to listen for tasks on {} {:?}", channel, error);
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// A queue that enqueues tasks inside a transaction managed by the caller.
+///
+/// Tasks are inserted with the caller's connection that is already in a transaction,
+/// so they become visible to workers only after the transaction is committed and are discarded
+/// if it is rolled back. This way tasks can be enqueued atomically with other changes
+/// of the application (the transactional outbox pattern).
+///
+///    ```rust
+///         connection.transaction::<_, QueueError, _>(|connection| {
+///             diesel::insert_into(orders::table).values(&order).execute(connection)?;
+///
+///             let mut queue = TransactionQueue::builder().connection(connection).build();
+///
+///             queue.insert_task(&SendOrderEmail { order_id: order.id })?;
+///
+///             Ok(())
+///         })?;
+///     ```
+///
+/// If `notifications` are enabled, workers are notified about the enqueued tasks
+/// when the transaction is committed.
+#[derive(TypedBuilder)]
+pub struct TransactionQueue<'a> {
+    connection: &'a mut PgConnection,
+    /// notifications controls if the queue notifies workers about new tasks with `NOTIFY`
+    #[builder(default = false, setter(into))]
+    notifications: bool,
+    /// The PostgreSQL schema of the queue whose workers are notified, it should match the `schema` of their queue
+    #[builder(default, setter(into, strip_option))]
+    schema: Option<String>,
+}
+
+impl TransactionQueue<'_> {
+    /// Enqueue a task in the transaction, see [`Queueable::insert_task`]
+    pub fn insert_task(&mut self, params: &dyn Runnable) -> Result<Task, QueueError> {
+        let task = Queue::insert_query(self.connection, params, Utc::now())?;
+
+        if self.notifications {
+            Queue::notify_task_query(self.connection, self.schema.as_deref(), &task)?;
+        }
+
+        Ok(task)
+    }
+
+    /// Enqueue multiple tasks in the transaction, see [`Queueable::insert_tasks`]
+    pub fn insert_tasks(&mut self, tasks: &[&dyn Runnable]) -> Result<Vec<Task>, QueueError> {
+        let tasks = Queue::insert_tasks_query(self.connection, tasks)?;
+
+        if self.notifications {
+            Queue::notify_tasks_query(self.connection, self.schema.as_deref(), &tasks)?;
+        }
+
+        Ok(tasks)
+    }
+
+    /// Schedule a task in the transaction, see [`Queueable::schedule_task`]
+    pub fn schedule_task(&mut self, params: &dyn Runnable) -> Result<Task, QueueError> {
+        Queue::schedule_task_query(self.connection, params)
+    }
 }
 
 impl Queueable for Queue {
@@ -168,10 +316,38 @@ impl Queueable for Queue {
         Self::fetch_and_touch_query(&mut connection, task_type)
     }
 
+    fn fetch_and_touch_tasks(
+        &self,
+        task_type: String,
+        limit: u32,
+    ) -> Result<Vec<Task>, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::fetch_and_touch_tasks_query(&mut connection, task_type, limit)
+    }
+
     fn insert_task(&self, params: &dyn Runnable) -> Result<Task, QueueError> {
         let mut connection = self.get_connection()?;
 
-        Self::insert_query(&mut connection, params, Utc::now())
+        let task = Self::insert_query(&mut connection, params, Utc::now())?;
+
+        if self.notifications {
+            Self::notify_task_query(&mut connection, self.schema.as_deref(), &task)?;
+        }
+
+        Ok(task)
+    }
+
+    fn insert_tasks(&self, tasks: &[&dyn Runnable]) -> Result<Vec<Task>, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        let tasks = Self::insert_tasks_query(&mut connection, tasks)?;
+
+        if self.notifications {
+            Self::notify_tasks_query(&mut connection, self.schema.as_deref(), &tasks)?;
+        }
+
+        Ok(tasks)
     }
     fn schedule_task(&self, params: &dyn Runnable) -> Result<Task, QueueError> {
         let mut connection = self.get_connection()?;
@@ -221,10 +397,26 @@ impl Queueable for Queue {
         Self::update_task_state_query(&mut connection, task, state)
     }
 
-    fn fail_task(&self, task: &Task, error: &str) -> Result<Task, QueueError> {
+    fn fail_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError> {
         let mut connection = self.get_connection()?;
 
-        Self::fail_task_query(&mut connection, task, error)
+        Self::fail_task_query(
+            &mut connection,
+            task,
+            &error.description,
+            error.details.as_ref(),
+        )
+    }
+
+    fn dead_letter_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::dead_letter_task_query(
+            &mut connection,
+            task,
+            &error.description,
+            error.details.as_ref(),
+        )
     }
 
     fn find_task_by_id(&self, id: Uuid) -> Option<Task> {
@@ -237,11 +429,102 @@ impl Queueable for Queue {
         &self,
         task: &Task,
         backoff_seconds: u32,
-        error: &str,
+        error: &FangError,
     ) -> Result<Task, QueueError> {
         let mut connection = self.get_connection()?;
 
-        Self::schedule_retry_query(&mut connection, task, backoff_seconds, error)
+        let task = Self::schedule_retry_query(
+            &mut connection,
+            task,
+            backoff_seconds,
+            &error.description,
+            error.details.as_ref(),
+        )?;
+
+        if self.notifications {
+            Self::notify_task_query(&mut connection, self.schema.as_deref(), &task)?;
+        }
+
+        Ok(task)
+    }
+
+    fn heartbeat_task(&self, task: &Task) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::heartbeat_task_query(&mut connection, task)
+    }
+
+    fn reap_expired_tasks(
+        &self,
+        heartbeat_timeout: std::time::Duration,
+    ) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::reap_expired_tasks_query(
+            &mut connection,
+            heartbeat_timeout,
+            self.record_reaped_attempts,
+        )
+    }
+
+    fn retry_failed_task(&self, id: Uuid) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        let task = Self::retry_failed_task_query(&mut connection, id)?;
+
+        if self.notifications {
+            Self::notify_task_query(&mut connection, self.schema.as_deref(), &task)?;
+        }
+
+        Ok(task)
+    }
+
+    fn retry_failed_tasks_of_type(&self, task_type: &str) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        let tasks = Self::retry_failed_tasks_of_type_query(&mut connection, task_type)?;
+
+        if self.notifications {
+            Self::notify_tasks_query(&mut connection, self.schema.as_deref(), &tasks)?;
+        }
+
+        Ok(tasks.len())
+    }
+
+    fn discard_dead_tasks(&self, older_than: std::time::Duration) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::discard_dead_tasks_query(&mut connection, older_than)
+    }
+
+    fn record_task_attempt(&self, attempt: &TaskAttempt) -> Result<(), QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::record_task_attempt_query(&mut connection, attempt)
+    }
+
+    fn find_task_attempts(&self, task_id: Uuid) -> Result<Vec<TaskAttempt>, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::find_task_attempts_query(&mut connection, task_id)
+    }
+
+    fn listen(&self, task_type: &str) -> Result<Option<TaskListener>, QueueError> {
+        if !self.notifications {
+            return Ok(None);
+        }
+
+        let database_url = match &self.database_url {
+            Some(database_url) => database_url.clone(),
+            None => return Err(QueueError::MissingDatabaseUrl),
+        };
+        let channel = notification_channel(self.schema.as_deref(), task_type);
+
+        // the connection isn't taken from the pool, it would be held by the listener forever
+        Ok(Some(TaskListener::listen(
+            move || Ok(PgConnection::establish(&database_url)?),
+            channel,
+        )?))
     }
 }
 
@@ -258,35 +541,104 @@ impl Queue {
         Ok(result.unwrap())
     }
 
+    /// Create a connection pool to the PostgreSQL database at `database_url` for a queue with a `schema`.
+    ///
+    /// The `search_path` of every connection is set to the schema followed by `public` once, when it's opened.
+    pub fn schema_connection_pool(
+        database_url: &str,
+        pool_size: u32,
+        schema: &str,
+    ) -> Result<r2d2::Pool<r2d2::ConnectionManager<PgConnection>>, QueueError> {
+        let manager = ConnectionManager::<PgConnection>::new(database_url);
+        let options = SchemaConnectionOptions {
+            schema: schema.to_string(),
+        };
+
+        Ok(r2d2::Pool::builder()
+            .max_size(pool_size)
+            .connection_customizer(Box::new(options))
+            .build(manager)?)
+    }
+
+    /// Apply the embedded migrations that were not applied yet and return their number.
+    ///
+    /// Applied migrations are recorded in the `fang_schema_migrations` table.
+    /// Migrations previously applied with the diesel CLI are only recorded there.
+    pub fn run_migrations(&self) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::run_migrations_query(&mut connection, self.schema.as_deref())
+    }
+
+    pub fn run_migrations_query(
+        connection: &mut PgConnection,
+        schema: Option<&str>,
+    ) -> Result<usize, QueueError> {
+        connection.transaction::<_, QueueError, _>(|connection| {
+            connection.batch_execute(migrations::LOCK_MIGRATIONS_QUERY)?;
+            connection.batch_execute(migrations::CREATE_EXTENSIONS_QUERY)?;
+
+            if let Some(schema) = schema {
+                connection.batch_execute(&migrations::create_schema_query(schema))?;
+            }
+
+            connection.batch_execute(migrations::CREATE_MIGRATIONS_TABLE_QUERY)?;
+
+            let applied: Vec<String> = diesel::sql_query(migrations::APPLIED_MIGRATIONS_QUERY)
+                .load::<MigrationVersion>(connection)?
+                .into_iter()
+                .map(|row| row.version)
+                .collect();
+
+            let diesel_migrations_exist =
+                diesel::sql_query(migrations::DIESEL_MIGRATIONS_EXIST_QUERY)
+                    .get_result::<DieselMigrationsExist>(connection)?
+                    .exists;
+
+            let diesel_applied: Vec<String> = if diesel_migrations_exist {
+                diesel::sql_query(migrations::DIESEL_MIGRATIONS_QUERY)
+                    .load::<MigrationVersion>(connection)?
+                    .into_iter()
+                    .map(|row| row.version)
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            let mut executed = 0;
+
+            for pending in migrations::pending_migrations(&applied, &diesel_applied) {
+                if !pending.applied_by_diesel {
+                    connection.batch_execute(pending.migration.sql)?;
+                    executed += 1;
+                }
+
+                diesel::sql_query(migrations::INSERT_MIGRATION_QUERY)
+                    .bind::<Text, _>(pending.migration.version)
+                    .execute(connection)?;
+            }
+
+            Ok(executed)
+        })
+    }
+
     pub fn schedule_task_query(
         connection: &mut PgConnection,
         params: &dyn Runnable,
     ) -> Result<Task, QueueError> {
-        let scheduled_at = match params.cron() {
-            Some(scheduled) => match scheduled {
-                CronPattern(cron_pattern) => {
-                    let schedule = Schedule::from_str(&cron_pattern)?;
-                    let mut iterator = schedule.upcoming(Utc);
-
-                    iterator
-                        .next()
-                        .ok_or(QueueError::CronError(CronError::NoTimestampsError))?
-                }
-                ScheduleOnce(datetime) => datetime,
-            },
-            None => {
-                return Err(QueueError::CronError(CronError::TaskNotSchedulableError));
-            }
-        };
+        let scheduled_at = Self::calculate_scheduled_at(params)?;
 
         Self::insert_query(connection, params, scheduled_at)
     }
 
-    fn calculate_hash(json: String) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(json.as_bytes());
-        let result = hasher.finalize();
-        hex::encode(result)
+    pub(crate) fn calculate_scheduled_at(
+        params: &dyn Runnable,
+    ) -> Result<DateTime<Utc>, QueueError> {
+        queueable::calculate_scheduled_at(params)
+    }
+
+    pub(crate) fn calculate_hash(json: String) -> String {
+        new_tasks::calculate_hash(json)
     }
 
     pub fn insert_query(
@@ -299,6 +651,7 @@ impl Queue {
                 .scheduled_at(scheduled_at)
                 .uniq_hash(None)
                 .task_type(params.task_type())
+                .priority(params.priority())
                 .metadata(serde_json::to_value(params).unwrap())
                 .build();
 
@@ -317,6 +670,7 @@ impl Queue {
                         .scheduled_at(scheduled_at)
                         .uniq_hash(Some(uniq_hash))
                         .task_type(params.task_type())
+                        .priority(params.priority())
                         .metadata(serde_json::to_value(params).unwrap())
                         .build();
 
@@ -328,6 +682,91 @@ impl Queue {
         }
     }
 
+    pub fn notify_task_query(
+        connection: &mut PgConnection,
+        schema: Option<&str>,
+        task: &Task,
+    ) -> Result<usize, QueueError> {
+        Ok(diesel::sql_query("SELECT pg_notify($1, $2)")
+            .bind::<Text, _>(notification_channel(schema, &task.task_type))
+            .bind::<Text, _>(task.id.to_string())
+            .execute(connection)?)
+    }
+
+    pub fn notify_tasks_query(
+        connection: &mut PgConnection,
+        schema: Option<&str>,
+        tasks: &[Task],
+    ) -> Result<(), QueueError> {
+        // one notification is enough to wake up workers of the same type
+        let mut task_types = HashSet::new();
+
+        for task in tasks.iter() {
+            if task_types.insert(task.task_type.as_str()) {
+                Self::notify_task_query(connection, schema, task)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn insert_tasks_query(
+        connection: &mut PgConnection,
+        tasks: &[&dyn Runnable],
+    ) -> Result<Vec<Task>, QueueError> {
+        let scheduled_at = Self::current_time();
+        let mut new_tasks = NewTasks::default();
+
+        for task in tasks {
+            new_tasks.add(
+                serde_json::to_value(task).unwrap(),
+                task.task_type(),
+                task.uniq(),
+                task.priority(),
+                scheduled_at,
+            );
+        }
+
+        connection.transaction::<Vec<Task>, QueueError, _>(|conn| {
+            let uniq_hashes = new_tasks.sorted_uniq_hashes();
+
+            // concurrent batches with the same unique tasks wait for each other until they are committed
+            if !uniq_hashes.is_empty() {
+                diesel::sql_query(LOCK_UNIQ_HASHES_QUERY)
+                    .bind::<Array<Text>, _>(&uniq_hashes)
+                    .execute(conn)?;
+            }
+
+            let mut stored_tasks = if new_tasks.is_empty() {
+                Vec::new()
+            } else {
+                diesel::sql_query(INSERT_TASKS_QUERY)
+                    .bind::<Array<diesel::sql_types::Uuid>, _>(&new_tasks.ids)
+                    .bind::<Array<Jsonb>, _>(&new_tasks.metadatas)
+                    .bind::<Array<Text>, _>(&new_tasks.task_types)
+                    .bind::<Array<Nullable<Text>>, _>(&new_tasks.uniq_hashes)
+                    .bind::<Array<Timestamptz>, _>(&new_tasks.scheduled_ats)
+                    .bind::<Array<SmallInt>, _>(&new_tasks.priorities)
+                    .load::<Task>(conn)?
+            };
+
+            // unique tasks that were already in the queue
+            if !uniq_hashes.is_empty() {
+                stored_tasks.extend(
+                    fang_tasks::table
+                        .filter(fang_tasks::uniq_hash.eq_any(&uniq_hashes))
+                        .filter(
+                            fang_tasks::state
+                                .eq_any(vec![FangTaskState::New, FangTaskState::Retried]),
+                        )
+                        .load::<Task>(conn)?,
+                );
+            }
+
+            Ok(new_tasks.into_stored_tasks(stored_tasks))
+        })
+    }
+
     pub fn fetch_task_query(connection: &mut PgConnection, task_type: String) -> Option<Task> {
         Self::fetch_task_of_type_query(connection, &task_type)
     }
@@ -336,21 +775,109 @@ impl Queue {
         connection: &mut PgConnection,
         task_type: String,
     ) -> Result<Option<Task>, QueueError> {
-        connection.transaction::<Option<Task>, QueueError, _>(|conn| {
-            let found_task = Self::fetch_task_query(conn, task_type);
+        let mut tasks = Self::fetch_and_touch_tasks_query(connection, task_type, 1)?;
 
-            if found_task.is_none() {
-                return Ok(None);
-            }
+        Ok(tasks.pop())
+    }
 
-            match Self::update_task_state_query(
-                conn,
-                &found_task.unwrap(),
-                FangTaskState::InProgress,
-            ) {
-                Ok(updated_task) => Ok(Some(updated_task)),
-                Err(err) => Err(err),
+    pub fn fetch_and_touch_tasks_query(
+        connection: &mut PgConnection,
+        task_type: String,
+        limit: u32,
+    ) -> Result<Vec<Task>, QueueError> {
+        let now = Self::current_time();
+
+        // the subquery is wrapped into an array so it's evaluated only once.
+        // Otherwise, Postgres can re-evaluate it for every updated row and claim more than `limit` tasks
+        let mut tasks = diesel::sql_query(FETCH_AND_TOUCH_TASKS_QUERY)
+            .bind::<Text, _>(task_type)
+            .bind::<Timestamptz, _>(now)
+            .bind::<BigInt, _>(limit as i64)
+            .load::<Task>(connection)?;
+
+        // `RETURNING` doesn't preserve the order of the subquery
+        tasks.sort_by(Task::fetch_order);
+
+        Ok(tasks)
+    }
+
+    pub fn heartbeat_task_query(
+        connection: &mut PgConnection,
+        task: &Task,
+    ) -> Result<usize, QueueError> {
+        let query = fang_tasks::table
+            .filter(fang_tasks::id.eq(task.id))
+            .filter(fang_tasks::state.eq(FangTaskState::InProgress))
+            .filter(fang_tasks::updated_at.eq(task.updated_at));
+
+        Ok(diesel::update(query)
+            .set(fang_tasks::heartbeat_at.eq(Self::current_time()))
+            .execute(connection)?)
+    }
+
+    pub fn reap_expired_tasks_query(
+        connection: &mut PgConnection,
+        heartbeat_timeout: std::time::Duration,
+        record_attempts: bool,
+    ) -> Result<usize, QueueError> {
+        let heartbeat_timeout =
+            Duration::from_std(heartbeat_timeout).map_err(|_| QueueError::TimeError)?;
+        let expired_at = Self::current_time() - heartbeat_timeout;
+
+        connection.transaction::<usize, QueueError, _>(|conn| {
+            let expired_tasks = fang_tasks::table
+                .filter(fang_tasks::state.eq(FangTaskState::InProgress))
+                .filter(
+                    fang_tasks::heartbeat_at
+                        .lt(expired_at)
+                        .or(fang_tasks::heartbeat_at
+                            .is_null()
+                            .and(fang_tasks::updated_at.lt(expired_at))),
+                )
+                .for_update()
+                .skip_locked()
+                .load::<Task>(conn)?;
+
+            for task in &expired_tasks {
+                let outcome =
+                    match serde_json::from_value::<Box<dyn Runnable>>(task.metadata.clone()) {
+                        Ok(runnable) if task.retries < runnable.max_retries() => {
+                            let backoff_seconds = runnable.backoff(task.retries as u32);
+
+                            Self::schedule_retry_query(
+                                conn,
+                                task,
+                                backoff_seconds,
+                                EXPIRED_HEARTBEAT_ERROR,
+                                None,
+                            )?;
+
+                            FangTaskState::Retried
+                        }
+                        Ok(_) => {
+                            Self::dead_letter_task_query(
+                                conn,
+                                task,
+                                EXPIRED_HEARTBEAT_ERROR,
+                                None,
+                            )?;
+
+                            FangTaskState::Dead
+                        }
+                        Err(_) => {
+                            Self::fail_task_query(conn, task, EXPIRED_HEARTBEAT_ERROR, None)?;
+
+                            FangTaskState::Failed
+                        }
+                    };
+
+                // the worker that executed the task can't record its attempt anymore
+                if record_attempts {
+                    Self::record_task_attempt_query(conn, &TaskAttempt::reaped(task, outcome))?;
+                }
             }
+
+            Ok(expired_tasks.len())
         })
     }
 
@@ -417,17 +944,126 @@ impl Queue {
     pub fn fail_task_query(
         connection: &mut PgConnection,
         task: &Task,
-        error: &str,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Result<Task, QueueError> {
+        Self::end_task_query(
+            connection,
+            task,
+            FangTaskState::Failed,
+            error_message,
+            error_details,
+        )
+    }
+
+    pub fn dead_letter_task_query(
+        connection: &mut PgConnection,
+        task: &Task,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Result<Task, QueueError> {
+        Self::end_task_query(
+            connection,
+            task,
+            FangTaskState::Dead,
+            error_message,
+            error_details,
+        )
+    }
+
+    /// Move a task to the final `state` of an unsuccessful task, `Failed` or `Dead`
+    fn end_task_query(
+        connection: &mut PgConnection,
+        task: &Task,
+        state: FangTaskState,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
     ) -> Result<Task, QueueError> {
         Ok(diesel::update(task)
             .set((
-                fang_tasks::state.eq(FangTaskState::Failed),
-                fang_tasks::error_message.eq(error),
+                fang_tasks::state.eq(state),
+                fang_tasks::error_message.eq(error_message),
+                fang_tasks::error_details.eq(error_details),
                 fang_tasks::updated_at.eq(Self::current_time()),
             ))
             .get_result::<Task>(connection)?)
     }
 
+    pub fn retry_failed_task_query(
+        connection: &mut PgConnection,
+        id: Uuid,
+    ) -> Result<Task, QueueError> {
+        let now = Self::current_time();
+
+        let query = fang_tasks::table
+            .filter(fang_tasks::id.eq(id))
+            .filter(fang_tasks::state.eq_any(vec![FangTaskState::Failed, FangTaskState::Dead]));
+
+        Ok(diesel::update(query)
+            .set((
+                fang_tasks::state.eq(FangTaskState::New),
+                fang_tasks::retries.eq(0),
+                fang_tasks::scheduled_at.eq(now),
+                fang_tasks::updated_at.eq(now),
+            ))
+            .get_result::<Task>(connection)?)
+    }
+
+    pub fn retry_failed_tasks_of_type_query(
+        connection: &mut PgConnection,
+        task_type: &str,
+    ) -> Result<Vec<Task>, QueueError> {
+        let now = Self::current_time();
+
+        let query = fang_tasks::table
+            .filter(fang_tasks::task_type.eq(task_type))
+            .filter(fang_tasks::state.eq_any(vec![FangTaskState::Failed, FangTaskState::Dead]));
+
+        Ok(diesel::update(query)
+            .set((
+                fang_tasks::state.eq(FangTaskState::New),
+                fang_tasks::retries.eq(0),
+                fang_tasks::scheduled_at.eq(now),
+                fang_tasks::updated_at.eq(now),
+            ))
+            .get_results::<Task>(connection)?)
+    }
+
+    pub fn discard_dead_tasks_query(
+        connection: &mut PgConnection,
+        older_than: std::time::Duration,
+    ) -> Result<usize, QueueError> {
+        let older_than = Duration::from_std(older_than).map_err(|_| QueueError::TimeError)?;
+        let updated_before = Self::current_time() - older_than;
+
+        let query = fang_tasks::table
+            .filter(fang_tasks::state.eq(FangTaskState::Dead))
+            .filter(fang_tasks::updated_at.lt(updated_before));
+
+        Ok(diesel::delete(query).execute(connection)?)
+    }
+
+    pub fn record_task_attempt_query(
+        connection: &mut PgConnection,
+        attempt: &TaskAttempt,
+    ) -> Result<(), QueueError> {
+        diesel::insert_into(fang_task_attempts::table)
+            .values(attempt)
+            .execute(connection)?;
+
+        Ok(())
+    }
+
+    pub fn find_task_attempts_query(
+        connection: &mut PgConnection,
+        task_id: Uuid,
+    ) -> Result<Vec<TaskAttempt>, QueueError> {
+        Ok(fang_task_attempts::table
+            .filter(fang_task_attempts::task_id.eq(task_id))
+            .order(fang_task_attempts::started_at.asc())
+            .load::<TaskAttempt>(connection)?)
+    }
+
     fn current_time() -> DateTime<Utc> {
         Utc::now()
     }
@@ -448,8 +1084,11 @@ impl Queue {
 
     fn fetch_task_of_type_query(connection: &mut PgConnection, task_type: &str) -> Option<Task> {
         fang_tasks::table
-            .order(fang_tasks::created_at.asc())
-            .order(fang_tasks::scheduled_at.asc())
+            .order((
+                fang_tasks::priority.desc(),
+                fang_tasks::scheduled_at.asc(),
+                fang_tasks::created_at.asc(),
+            ))
             .limit(1)
             .filter(fang_tasks::scheduled_at.le(Utc::now()))
             .filter(fang_tasks::state.eq_any(vec![FangTaskState::New, FangTaskState::Retried]))
@@ -475,7 +1114,8 @@ impl Queue {
         connection: &mut PgConnection,
         task: &Task,
         backoff_seconds: u32,
-        error: &str,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
     ) -> Result<Task, QueueError> {
         let now = Self::current_time();
         let scheduled_at = now + Duration::seconds(backoff_seconds as i64);
@@ -483,7 +1123,8 @@ impl Queue {
         let task = diesel::update(task)
             .set((
                 fang_tasks::state.eq(FangTaskState::Retried),
-                fang_tasks::error_message.eq(error),
+                fang_tasks::error_message.eq(error_message),
+                fang_tasks::error_details.eq(error_details),
                 fang_tasks::retries.eq(task.retries + 1),
                 fang_tasks::scheduled_at.eq(scheduled_at),
                 fang_tasks::updated_at.eq(now),
@@ -497,20 +1138,35 @@ impl Queue {
 #[cfg(test)]
 mod queue_tests {
     use super::Queue;
+    use super::QueueError;
     use super::Queueable;
+    use super::Task;
+    use super::TaskAttempt;
+    use super::TransactionQueue;
     use crate::chrono::SubsecRound;
-    use crate::fang_task_state::FangTaskState;
+    use crate::migrations;
     use crate::runnable::Runnable;
     use crate::runnable::COMMON_TYPE;
+    use crate::task::FangTaskState;
     use crate::typetag;
     use crate::FangError;
     use crate::Scheduled;
+    use crate::TaskContext;
+    use crate::EXPIRED_HEARTBEAT_ERROR;
+    use crate::REAPER_WORKER_ID;
     use chrono::DateTime;
     use chrono::Duration;
     use chrono::Utc;
     use diesel::connection::Connection;
+    use diesel::connection::SimpleConnection;
+    use diesel::pg::PgConnection;
     use diesel::result::Error;
+    use diesel::sql_types::Text;
+    use diesel::RunQueryDsl;
+    use dotenv::dotenv;
     use serde::{Deserialize, Serialize};
+    use std::env;
+    use uuid::Uuid;
 
     #[derive(Serialize, Deserialize)]
     struct PepeTask {
@@ -519,7 +1175,7 @@ mod queue_tests {
 
     #[typetag::serde]
     impl Runnable for PepeTask {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             println!("the number is {}", self.number);
 
             Ok(())
@@ -529,6 +1185,24 @@ mod queue_tests {
         }
     }
 
+    #[derive(Serialize, Deserialize)]
+    struct UrgentTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for UrgentTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            println!("the number is {}", self.number);
+
+            Ok(())
+        }
+
+        fn priority(&self) -> i16 {
+            10
+        }
+    }
+
     #[derive(Serialize, Deserialize)]
     struct AyratTask {
         pub number: u16,
@@ -536,7 +1210,7 @@ mod queue_tests {
 
     #[typetag::serde]
     impl Runnable for AyratTask {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             println!("the number is {}", self.number);
 
             Ok(())
@@ -550,6 +1224,22 @@ mod queue_tests {
         }
     }
 
+    #[derive(Serialize, Deserialize)]
+    struct DeadTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for DeadTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn task_type(&self) -> String {
+            "dead_letters_test".to_string()
+        }
+    }
+
     #[derive(Serialize, Deserialize)]
     struct ScheduledPepeTask {
         pub number: u16,
@@ -558,7 +1248,7 @@ mod queue_tests {
 
     #[typetag::serde]
     impl Runnable for ScheduledPepeTask {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             println!("the number is {}", self.number);
 
             Ok(())
@@ -577,6 +1267,142 @@ mod queue_tests {
         }
     }
 
+    #[test]
+    fn run_migrations_query_applies_pending_migrations() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            // an empty schema that shadows the tables of the test database
+            conn.batch_execute(
+                "CREATE SCHEMA fang_migrations_test;
+                 SET LOCAL search_path TO fang_migrations_test, public;
+                 CREATE TABLE __diesel_schema_migrations (version VARCHAR(50) PRIMARY KEY);
+                 INSERT INTO __diesel_schema_migrations VALUES ('00000000000000');",
+            )?;
+
+            let executed = Queue::run_migrations_query(conn, None).unwrap();
+            assert_eq!(migrations::MIGRATIONS.len(), executed);
+
+            let executed = Queue::run_migrations_query(conn, None).unwrap();
+            assert_eq!(0, executed);
+
+            let task = Queue::insert_query(conn, &UrgentTask { number: 1 }, Utc::now()).unwrap();
+            assert_eq!(10, task.priority);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn run_migrations_query_records_migrations_applied_by_diesel() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            conn.batch_execute(
+                "CREATE SCHEMA fang_diesel_migrations_test;
+                 SET LOCAL search_path TO fang_diesel_migrations_test, public;
+                 CREATE TABLE __diesel_schema_migrations (version VARCHAR(50) PRIMARY KEY);",
+            )?;
+
+            for migration in migrations::MIGRATIONS {
+                diesel::sql_query("INSERT INTO __diesel_schema_migrations VALUES ($1)")
+                    .bind::<Text, _>(migrations::diesel_version(migration.version))
+                    .execute(conn)?;
+            }
+
+            let executed = Queue::run_migrations_query(conn, None).unwrap();
+            assert_eq!(0, executed);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn queue_with_schema_stores_tasks_in_schema() {
+        dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let pool = Queue::schema_connection_pool(&database_url, 1, "fang_schema_test").unwrap();
+
+        let queue = Queue::builder()
+            .connection_pool(pool)
+            .schema("fang_schema_test")
+            .build();
+
+        let mut connection = queue.connection_pool.get().unwrap();
+        connection
+            .batch_execute("DROP SCHEMA IF EXISTS fang_schema_test CASCADE")
+            .unwrap();
+        drop(connection);
+
+        queue.run_migrations().unwrap();
+
+        let task = queue.insert_task(&PepeTask { number: 10 }).unwrap();
+
+        let mut connection = queue.get_connection().unwrap();
+        let schema_tasks = diesel::sql_query("SELECT * FROM fang_schema_test.fang_tasks")
+            .load::<Task>(&mut connection)
+            .unwrap();
+        let public_tasks = diesel::sql_query("SELECT * FROM public.fang_tasks WHERE id = $1")
+            .bind::<diesel::sql_types::Uuid, _>(task.id)
+            .load::<Task>(&mut connection)
+            .unwrap();
+
+        connection
+            .batch_execute("DROP SCHEMA fang_schema_test CASCADE")
+            .unwrap();
+
+        assert_eq!(vec![task], schema_tasks);
+        assert!(public_tasks.is_empty());
+    }
+
+    #[test]
+    fn run_migrations_migrates_queues_of_different_schemas_on_fresh_database() {
+        dotenv().ok();
+
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+        let (server_url, _) = database_url.rsplit_once('/').unwrap();
+        let fresh_database_url = format!("{}/fang_fresh_database_test", server_url);
+
+        let mut connection = PgConnection::establish(&database_url).unwrap();
+        connection
+            .batch_execute("DROP DATABASE IF EXISTS fang_fresh_database_test")
+            .unwrap();
+        connection
+            .batch_execute("CREATE DATABASE fang_fresh_database_test")
+            .unwrap();
+
+        let tasks: Vec<Result<Task, QueueError>> = ["fang_schema_a", "fang_schema_b"]
+            .iter()
+            .map(|schema| {
+                let pool = Queue::schema_connection_pool(&fresh_database_url, 1, schema)?;
+                let queue = Queue::builder()
+                    .connection_pool(pool)
+                    .schema(*schema)
+                    .build();
+
+                queue.run_migrations()?;
+                queue.insert_task(&PepeTask { number: 10 })
+            })
+            .collect();
+
+        connection
+            .batch_execute("DROP DATABASE fang_fresh_database_test")
+            .unwrap();
+
+        for task in tasks {
+            assert_eq!(FangTaskState::New, task.unwrap().state);
+        }
+    }
+
     #[test]
     fn insert_task_test() {
         let task = PepeTask { number: 10 };
@@ -667,8 +1493,9 @@ mod queue_tests {
             let task = Queue::insert_query(conn, &task, Utc::now()).unwrap();
 
             let error = "Failed".to_string();
+            let details = serde_json::json!({"code": 42});
 
-            let found_task = Queue::fail_task_query(conn, &task, &error).unwrap();
+            let found_task = Queue::fail_task_query(conn, &task, &error, Some(&details)).unwrap();
 
             let metadata = found_task.metadata.as_object().unwrap();
             let number = metadata["number"].as_u64();
@@ -679,6 +1506,123 @@ mod queue_tests {
             assert_eq!(Some(10), number);
             assert_eq!(Some("PepeTask"), type_task);
             assert_eq!(found_task.error_message.unwrap(), error);
+            assert_eq!(found_task.error_details, Some(details));
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn retry_failed_tasks_test() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let task1 = Queue::insert_query(conn, &DeadTask { number: 1 }, Utc::now()).unwrap();
+            let task2 = Queue::insert_query(conn, &DeadTask { number: 2 }, Utc::now()).unwrap();
+            let task3 = Queue::insert_query(conn, &DeadTask { number: 3 }, Utc::now()).unwrap();
+
+            Queue::fail_task_query(conn, &task1, "Failed", None).unwrap();
+            let task2 = Queue::schedule_retry_query(conn, &task2, 0, "Failed", None).unwrap();
+            let task2 = Queue::dead_letter_task_query(conn, &task2, "Failed", None).unwrap();
+            assert_eq!(FangTaskState::Dead, task2.state);
+
+            let retried_task = Queue::retry_failed_task_query(conn, task1.id).unwrap();
+
+            assert_eq!(FangTaskState::New, retried_task.state);
+            assert_eq!(0, retried_task.retries);
+            assert_eq!(Some("Failed"), retried_task.error_message.as_deref());
+            assert!(Queue::retry_failed_task_query(conn, task3.id).is_err());
+
+            let retried_tasks =
+                Queue::retry_failed_tasks_of_type_query(conn, "dead_letters_test").unwrap();
+
+            assert_eq!(1, retried_tasks.len());
+            assert_eq!(task2.id, retried_tasks[0].id);
+            assert_eq!(FangTaskState::New, retried_tasks[0].state);
+            assert_eq!(0, retried_tasks[0].retries);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn discard_dead_tasks_test() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let task1 = Queue::insert_query(conn, &DeadTask { number: 1 }, Utc::now()).unwrap();
+            let task2 = Queue::insert_query(conn, &DeadTask { number: 2 }, Utc::now()).unwrap();
+
+            Queue::dead_letter_task_query(conn, &task1, "Failed", None).unwrap();
+            Queue::fail_task_query(conn, &task2, "Failed", None).unwrap();
+
+            Queue::discard_dead_tasks_query(conn, std::time::Duration::from_secs(60)).unwrap();
+            assert!(Queue::find_task_by_id_query(conn, task1.id).is_some());
+
+            let discarded =
+                Queue::discard_dead_tasks_query(conn, std::time::Duration::ZERO).unwrap();
+
+            assert!(discarded >= 1);
+            assert!(Queue::find_task_by_id_query(conn, task1.id).is_none());
+            assert!(Queue::find_task_by_id_query(conn, task2.id).is_some());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn task_attempts_test() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let task = Queue::insert_query(conn, &PepeTask { number: 10 }, Utc::now()).unwrap();
+            let started_at = Utc::now().round_subsecs(0);
+
+            let second_attempt = TaskAttempt::builder()
+                .id(Uuid::new_v4())
+                .task_id(task.id)
+                .attempt(2)
+                .worker_id("worker_common1")
+                .outcome(FangTaskState::Finished)
+                .started_at(started_at + Duration::seconds(10))
+                .finished_at(started_at + Duration::seconds(11))
+                .build();
+            let first_attempt = TaskAttempt::builder()
+                .id(Uuid::new_v4())
+                .task_id(task.id)
+                .attempt(1)
+                .worker_id("worker_common2")
+                .outcome(FangTaskState::Retried)
+                .error_message(Some("Failed".to_string()))
+                .error_details(Some(serde_json::json!({"status": 503})))
+                .started_at(started_at)
+                .finished_at(started_at + Duration::seconds(1))
+                .build();
+
+            Queue::record_task_attempt_query(conn, &second_attempt).unwrap();
+            Queue::record_task_attempt_query(conn, &first_attempt).unwrap();
+
+            let attempts = Queue::find_task_attempts_query(conn, task.id).unwrap();
+
+            assert_eq!(vec![first_attempt, second_attempt], attempts);
+
+            Queue::remove_task_query(conn, task.id).unwrap();
+
+            let attempts = Queue::find_task_attempts_query(conn, task.id).unwrap();
+
+            assert!(attempts.is_empty());
 
             Ok(())
         });
@@ -731,6 +1675,140 @@ mod queue_tests {
         });
     }
 
+    #[test]
+    fn fetch_and_touch_respects_priority() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let task = Queue::insert_query(conn, &PepeTask { number: 10 }, Utc::now()).unwrap();
+            assert_eq!(0, task.priority);
+
+            let urgent_task =
+                Queue::insert_query(conn, &UrgentTask { number: 11 }, Utc::now()).unwrap();
+            assert_eq!(10, urgent_task.priority);
+
+            let found_task = Queue::fetch_and_touch_query(conn, COMMON_TYPE.to_string())
+                .unwrap()
+                .unwrap();
+            assert_eq!(urgent_task.id, found_task.id);
+
+            let found_task = Queue::fetch_and_touch_query(conn, COMMON_TYPE.to_string())
+                .unwrap()
+                .unwrap();
+            assert_eq!(task.id, found_task.id);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn fetch_and_touch_tasks_test() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let task1 = Queue::insert_query(conn, &PepeTask { number: 10 }, Utc::now()).unwrap();
+            let task2 = Queue::insert_query(conn, &PepeTask { number: 11 }, Utc::now()).unwrap();
+            let urgent_task =
+                Queue::insert_query(conn, &UrgentTask { number: 12 }, Utc::now()).unwrap();
+
+            let tasks =
+                Queue::fetch_and_touch_tasks_query(conn, COMMON_TYPE.to_string(), 2).unwrap();
+
+            assert_eq!(2, tasks.len());
+            assert_eq!(urgent_task.id, tasks[0].id);
+            assert_eq!(task1.id, tasks[1].id);
+            assert!(tasks
+                .iter()
+                .all(|task| task.state == FangTaskState::InProgress));
+
+            let tasks =
+                Queue::fetch_and_touch_tasks_query(conn, COMMON_TYPE.to_string(), 2).unwrap();
+
+            assert_eq!(1, tasks.len());
+            assert_eq!(task2.id, tasks[0].id);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn insert_tasks_test() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let existing_task =
+                Queue::insert_query(conn, &PepeTask { number: 2 }, Utc::now()).unwrap();
+
+            let tasks = Queue::insert_tasks_query(
+                conn,
+                &[
+                    &UrgentTask { number: 1 },
+                    &PepeTask { number: 1 },
+                    &PepeTask { number: 1 },
+                    &PepeTask { number: 2 },
+                ],
+            )
+            .unwrap();
+
+            assert_eq!(4, tasks.len());
+
+            assert_eq!(Some("UrgentTask"), tasks[0].metadata["type"].as_str());
+            assert_eq!(None, tasks[0].uniq_hash);
+            assert_eq!(10, tasks[0].priority);
+            assert_eq!(FangTaskState::New, tasks[0].state);
+
+            assert_eq!(Some("PepeTask"), tasks[1].metadata["type"].as_str());
+            assert!(tasks[1].uniq_hash.is_some());
+            assert_eq!(tasks[1].id, tasks[2].id);
+            assert_eq!(existing_task.id, tasks[3].id);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn transaction_queue_enqueues_tasks_in_caller_transaction() {
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        let mut task_id = None;
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let mut transaction_queue = TransactionQueue::builder().connection(conn).build();
+
+            let task = transaction_queue
+                .insert_task(&PepeTask { number: 11 })
+                .unwrap();
+
+            let found_task = Queue::find_task_by_id_query(conn, task.id).unwrap();
+
+            assert_eq!(task.id, found_task.id);
+
+            task_id = Some(task.id);
+
+            Ok(())
+        });
+
+        let task_id = task_id.unwrap();
+
+        assert!(Queue::find_task_by_id_query(&mut queue_pooled_connection, task_id).is_none());
+    }
+
     #[test]
     fn insert_task_uniq_test() {
         let task = PepeTask { number: 10 };
@@ -919,4 +1997,141 @@ mod queue_tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn reap_expired_tasks_retries_tasks_with_expired_heartbeat() {
+        let task = PepeTask { number: 10 };
+
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let task = Queue::insert_query(conn, &task, Utc::now()).unwrap();
+            Queue::fetch_and_touch_query(conn, COMMON_TYPE.to_string())
+                .unwrap()
+                .unwrap();
+
+            let number =
+                Queue::reap_expired_tasks_query(conn, std::time::Duration::ZERO, true).unwrap();
+            assert_eq!(1, number);
+
+            let found_task = Queue::find_task_by_id_query(conn, task.id).unwrap();
+
+            assert_eq!(FangTaskState::Retried, found_task.state);
+            assert_eq!(1, found_task.retries);
+            assert_eq!(
+                Some(EXPIRED_HEARTBEAT_ERROR.to_string()),
+                found_task.error_message
+            );
+
+            let attempts = Queue::find_task_attempts_query(conn, task.id).unwrap();
+            assert_eq!(1, attempts.len());
+            assert_eq!(REAPER_WORKER_ID, attempts[0].worker_id);
+            assert_eq!(FangTaskState::Retried, attempts[0].outcome);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reap_expired_tasks_skips_tasks_with_heartbeat() {
+        let task = PepeTask { number: 10 };
+
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let mut queue_pooled_connection = queue.connection_pool.get().unwrap();
+
+        queue_pooled_connection.test_transaction::<(), Error, _>(|conn| {
+            let task = Queue::insert_query(conn, &task, Utc::now()).unwrap();
+            let found_task = Queue::fetch_and_touch_query(conn, COMMON_TYPE.to_string())
+                .unwrap()
+                .unwrap();
+
+            assert!(found_task.heartbeat_at.is_some());
+
+            let number =
+                Queue::reap_expired_tasks_query(conn, std::time::Duration::from_secs(60), true)
+                    .unwrap();
+            assert_eq!(0, number);
+
+            let found_task = Queue::find_task_by_id_query(conn, task.id).unwrap();
+            assert_eq!(FangTaskState::InProgress, found_task.state);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn listener_receives_notifications() {
+        let pool = Queue::connection_pool(5);
+        let database_url = env::var("DATABASE_URL").unwrap();
+
+        let queue = Queue::builder()
+            .connection_pool(pool.clone())
+            .notifications(true)
+            .database_url(database_url.clone())
+            .build();
+
+        let other_schema_queue = Queue::builder()
+            .connection_pool(pool.clone())
+            .notifications(true)
+            .database_url(database_url)
+            .schema("fang_other_schema")
+            .build();
+
+        let queue_without_url = Queue::builder()
+            .connection_pool(pool)
+            .notifications(true)
+            .build();
+
+        assert!(matches!(
+            queue_without_url.listen("notifications_test"),
+            Err(QueueError::MissingDatabaseUrl)
+        ));
+
+        let mut listener = queue.listen("notifications_test").unwrap().unwrap();
+        let mut shared_listener = listener.clone();
+        let mut other_schema_listener = other_schema_queue
+            .listen("notifications_test")
+            .unwrap()
+            .unwrap();
+
+        assert!(!listener.has_notifications());
+
+        let task = Task::builder()
+            .id(Uuid::new_v4())
+            .metadata(serde_json::json!({}))
+            .error_message(None)
+            .state(FangTaskState::New)
+            .task_type("notifications_test")
+            .uniq_hash(None)
+            .retries(0)
+            .scheduled_at(Utc::now())
+            .created_at(Utc::now())
+            .updated_at(Utc::now())
+            .build();
+
+        let mut connection = queue.get_connection().unwrap();
+        Queue::notify_task_query(&mut connection, None, &task).unwrap();
+
+        let mut notified = false;
+
+        for _ in 0..50 {
+            if listener.has_notifications() {
+                notified = true;
+                break;
+            }
+
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        assert!(notified);
+        assert!(shared_listener.has_notifications());
+        assert!(!other_schema_listener.has_notifications());
+    }
 }
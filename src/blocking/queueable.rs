@@ -0,0 +1,236 @@
+use crate::runnable::Runnable;
+use crate::task::FangTaskState;
+use crate::CronError;
+use crate::FangError;
+use crate::Scheduled::*;
+use chrono::DateTime;
+use chrono::Utc;
+use cron::Schedule;
+use diesel::r2d2::PoolError;
+use diesel::result::Error as DieselError;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use thiserror::Error;
+use uuid::Uuid;
+
+pub use crate::task::Task;
+pub use crate::task::TaskAttempt;
+
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error(transparent)]
+    DieselError(#[from] DieselError),
+    #[error(transparent)]
+    PoolError(#[from] PoolError),
+    #[error(transparent)]
+    ConnectionError(#[from] diesel::ConnectionError),
+    #[error(transparent)]
+    CronError(#[from] CronError),
+    #[error("Can not perform this operation if task is not uniq, please check its definition in impl Runnable")]
+    TaskNotUniqError,
+    #[error("Can not convert `std::time::Duration` to `chrono::Duration`")]
+    TimeError,
+    #[error("{0} is not supported by this queue")]
+    Unsupported(&'static str),
+    #[error("The queue needs a database_url to listen for notifications")]
+    MissingDatabaseUrl,
+}
+
+impl From<cron::error::Error> for QueueError {
+    fn from(error: cron::error::Error) -> Self {
+        QueueError::CronError(CronError::LibraryError(error))
+    }
+}
+
+/// This trait defines operations for a synchronous queue.
+/// The trait can be implemented for different storage backends.
+/// For now, the trait is implemented for PostgreSQL ([`Queue`]) and for the in-memory storage ([`InMemoryQueue`](crate::blocking::InMemoryQueue)).
+pub trait Queueable {
+    /// This method should retrieve one task of the `task_type` type. If `task_type` is `None` it will try to
+    /// fetch a task of the type `common`. After fetching it should update the state of the task to
+    /// `FangTaskState::InProgress`.
+    fn fetch_and_touch_task(&self, task_type: String) -> Result<Option<Task>, QueueError>;
+
+    /// This method should retrieve up to `limit` tasks of the `task_type` type in one go.
+    /// After fetching it should update the state of the tasks to `FangTaskState::InProgress`.
+    /// Tasks are returned in the order they should be executed.
+    fn fetch_and_touch_tasks(&self, task_type: String, limit: u32)
+        -> Result<Vec<Task>, QueueError>;
+
+    /// Enqueue a task to the queue, The task will be executed as soon as possible by the worker of the same type
+    /// created by an `WorkerPool`.
+    fn insert_task(&self, params: &dyn Runnable) -> Result<Task, QueueError>;
+
+    /// Enqueue multiple tasks with one query. Tasks are handled the same way as in `insert_task`,
+    /// unique tasks that are already in the queue are not inserted again.
+    /// The returned tasks are in the same order as `tasks`.
+    fn insert_tasks(&self, _tasks: &[&dyn Runnable]) -> Result<Vec<Task>, QueueError> {
+        Err(QueueError::Unsupported("insert_tasks"))
+    }
+
+    /// The method will remove all tasks from the queue
+    fn remove_all_tasks(&self) -> Result<usize, QueueError>;
+
+    /// Remove all tasks that are scheduled in the future.
+    fn remove_all_scheduled_tasks(&self) -> Result<usize, QueueError>;
+
+    /// Removes all tasks that have the specified `task_type`.
+    fn remove_tasks_of_type(&self, task_type: &str) -> Result<usize, QueueError>;
+
+    /// Remove a task by its id.
+    fn remove_task(&self, id: Uuid) -> Result<usize, QueueError>;
+
+    /// To use this function task has to be uniq. uniq() has to return true.
+    /// If task is not uniq this function will not do anything.
+    /// Remove a task by its metadata (struct fields values)
+    fn remove_task_by_metadata(&self, task: &dyn Runnable) -> Result<usize, QueueError>;
+
+    fn find_task_by_id(&self, id: Uuid) -> Option<Task>;
+
+    /// Update the state field of the specified task
+    /// See the `FangTaskState` enum for possible states.
+    fn update_task_state(&self, task: &Task, state: FangTaskState) -> Result<Task, QueueError>;
+
+    /// Update the state of a task to `FangTaskState::Failed` and save the `error`
+    /// as its `error_message` and `error_details`.
+    fn fail_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError>;
+
+    /// Update the state of a task that used up its retries to `FangTaskState::Dead` and save the `error`.
+    ///
+    /// Dead tasks are kept regardless of the retention mode. Queues without dead letters
+    /// fail the task instead, which is the default.
+    fn dead_letter_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError> {
+        self.fail_task(task, error)
+    }
+
+    /// Schedule a task.
+    fn schedule_task(&self, task: &dyn Runnable) -> Result<Task, QueueError>;
+
+    /// Schedule the next attempt of a failed task in `backoff_in_seconds` and save the `error`.
+    fn schedule_retry(
+        &self,
+        task: &Task,
+        backoff_in_seconds: u32,
+        error: &FangError,
+    ) -> Result<Task, QueueError>;
+
+    /// Update the heartbeat of a task that is being executed.
+    /// Workers call this method periodically while they are executing a task.
+    ///
+    /// The heartbeat is updated only if the task is still in the `FangTaskState::InProgress` state
+    /// and its `updated_at` is the one the worker got when it fetched the task.
+    /// Returns 0 if the task was returned to the queue by `reap_expired_tasks` in the meantime.
+    fn heartbeat_task(&self, _task: &Task) -> Result<usize, QueueError> {
+        Err(QueueError::Unsupported("heartbeat_task"))
+    }
+
+    /// Return tasks that are stuck in the `FangTaskState::InProgress` state back to the queue.
+    ///
+    /// A task is stuck if its worker has not sent a heartbeat for longer than `heartbeat_timeout`.
+    /// Such tasks are retried if they have not exhausted their `max_retries`, otherwise they become dead.
+    /// Tasks that can't be deserialized anymore are failed.
+    /// Queues that store the history of tasks record the reaped executions with the `REAPER_WORKER_ID`
+    /// if they are configured to record reaped attempts.
+    /// Returns the number of affected tasks.
+    fn reap_expired_tasks(
+        &self,
+        _heartbeat_timeout: std::time::Duration,
+    ) -> Result<usize, QueueError> {
+        Err(QueueError::Unsupported("reap_expired_tasks"))
+    }
+
+    /// Return a task in the `FangTaskState::Dead` or the `FangTaskState::Failed` state back to the queue.
+    ///
+    /// The task is executed again as soon as possible and gets its `max_retries` again.
+    /// The error of its last attempt is kept until the next attempt.
+    fn retry_failed_task(&self, _id: Uuid) -> Result<Task, QueueError> {
+        Err(QueueError::Unsupported("retry_failed_task"))
+    }
+
+    /// Return all dead and failed tasks of `task_type` back to the queue, see `retry_failed_task`.
+    /// Returns the number of affected tasks.
+    fn retry_failed_tasks_of_type(&self, _task_type: &str) -> Result<usize, QueueError> {
+        Err(QueueError::Unsupported("retry_failed_tasks_of_type"))
+    }
+
+    /// Remove dead tasks that were not updated for longer than `older_than`.
+    /// Returns the number of removed tasks.
+    fn discard_dead_tasks(&self, _older_than: std::time::Duration) -> Result<usize, QueueError> {
+        Err(QueueError::Unsupported("discard_dead_tasks"))
+    }
+
+    /// Save an attempt to execute a task, workers call it after every execution if `record_attempts` is enabled.
+    /// The attempts of tasks that are removed by the retention mode aren't recorded.
+    ///
+    /// Queues that don't store the history of tasks return `Unsupported`, which is the default.
+    fn record_task_attempt(&self, _attempt: &TaskAttempt) -> Result<(), QueueError> {
+        Err(QueueError::Unsupported("record_task_attempt"))
+    }
+
+    /// Return the recorded attempts of the task with `task_id`, from the oldest to the newest.
+    ///
+    /// The history of a task is removed together with the task.
+    /// Returns `Unsupported` if the queue doesn't store the history of tasks, which is the default.
+    fn find_task_attempts(&self, _task_id: Uuid) -> Result<Vec<TaskAttempt>, QueueError> {
+        Err(QueueError::Unsupported("find_task_attempts"))
+    }
+
+    /// Start listening for notifications about new tasks of `task_type`.
+    ///
+    /// Workers use the returned `TaskListener` to wake up as soon as a task is enqueued.
+    /// Returns `None` if the queue doesn't send notifications, which is the default.
+    fn listen(&self, _task_type: &str) -> Result<Option<TaskListener>, QueueError> {
+        Ok(None)
+    }
+}
+
+/// Receives notifications about new tasks of one type.
+///
+/// A background thread listens on a dedicated connection to the database of the queue and reconnects if the connection fails.
+/// Clones of a listener share this connection, so all workers of a `WorkerPool` use one connection to listen on.
+/// The thread stops and closes the connection when all clones are dropped.
+/// The listener is created by [`Queueable::listen`].
+#[derive(Clone)]
+pub struct TaskListener {
+    notifications: Arc<AtomicU64>,
+    received: u64,
+}
+
+impl TaskListener {
+    /// A listener that checks `notifications`, the number of received notifications
+    #[cfg(feature = "blocking")]
+    pub(crate) fn new(notifications: Arc<AtomicU64>) -> Self {
+        TaskListener {
+            notifications,
+            received: 0,
+        }
+    }
+
+    /// Check if notifications were received since the last call. It doesn't block.
+    pub fn has_notifications(&mut self) -> bool {
+        let notifications = self.notifications.load(Ordering::SeqCst);
+        let received = notifications != self.received;
+
+        self.received = notifications;
+
+        received
+    }
+}
+
+/// The time of the next execution of a scheduled task
+pub(crate) fn calculate_scheduled_at(params: &dyn Runnable) -> Result<DateTime<Utc>, QueueError> {
+    match params.cron() {
+        Some(CronPattern(cron_pattern)) => {
+            let schedule = Schedule::from_str(&cron_pattern)?;
+            let mut iterator = schedule.upcoming(Utc);
+
+            iterator
+                .next()
+                .ok_or(QueueError::CronError(CronError::NoTimestampsError))
+        }
+        Some(ScheduleOnce(datetime)) => Ok(datetime),
+        None => Err(QueueError::CronError(CronError::TaskNotSchedulableError)),
+    }
+}
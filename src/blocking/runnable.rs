@@ -1,6 +1,8 @@
-use crate::queue::Queueable;
+use crate::queueable::Queueable;
 use crate::FangError;
 use crate::Scheduled;
+use crate::TaskContext;
+use std::time::Duration;
 
 pub const COMMON_TYPE: &str = "common";
 pub const RETRIES_NUMBER: i32 = 20;
@@ -8,8 +10,10 @@ pub const RETRIES_NUMBER: i32 = 20;
 /// Implement this trait to run your custom tasks.
 #[typetag::serde(tag = "type")]
 pub trait Runnable {
-    /// Execute the task. This method should define its logic
-    fn run(&self, _queueable: &dyn Queueable) -> Result<(), FangError>;
+    /// Execute the task. This method should define its logic.
+    ///
+    /// The `context` describes the current execution of the task, for example the number of its retries
+    fn run(&self, queueable: &dyn Queueable, context: &TaskContext) -> Result<(), FangError>;
 
     /// Define the type of the task.
     /// The `common` task type is used by default
@@ -50,4 +54,25 @@ pub trait Runnable {
     fn backoff(&self, attempt: u32) -> u32 {
         u32::pow(2, attempt)
     }
+
+    /// Define the priority of the task.
+    /// Tasks with a higher priority are executed before tasks with a lower priority
+    /// of the same `task_type`, tasks with the same priority are executed in the order they were scheduled.
+    /// By default, the priority is 0.
+    fn priority(&self) -> i16 {
+        0
+    }
+
+    /// Define the maximum duration of the task execution.
+    /// If the task is not finished in time, it fails with a timeout error and it's retried
+    /// the same way as any other failed task.
+    ///
+    /// Be careful, a thread can not be interrupted, so the timeout does not stop the work of the task.
+    /// The worker stops waiting for the task and abandons its thread, but the task keeps running
+    /// until it's finished, possibly at the same time as its retry. Tasks with a timeout
+    /// should be safe to execute more than once.
+    /// By default, there is no timeout.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
 }
@@ -1,11 +1,28 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
-    #[derive(diesel::sql_types::SqlType)]
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "fang_task_state"))]
     pub struct FangTaskState;
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::FangTaskState;
+
+    fang_task_attempts (id) {
+        id -> Uuid,
+        task_id -> Uuid,
+        attempt -> Int4,
+        worker_id -> Varchar,
+        outcome -> FangTaskState,
+        error_message -> Nullable<Text>,
+        error_details -> Nullable<Jsonb>,
+        started_at -> Timestamptz,
+        finished_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::FangTaskState;
@@ -21,5 +38,12 @@ diesel::table! {
         scheduled_at -> Timestamptz,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        heartbeat_at -> Nullable<Timestamptz>,
+        priority -> Int2,
+        error_details -> Nullable<Jsonb>,
     }
 }
+
+diesel::joinable!(fang_task_attempts -> fang_tasks (task_id));
+
+diesel::allow_tables_to_appear_in_same_query!(fang_task_attempts, fang_tasks,);
@@ -0,0 +1,1160 @@
+use crate::new_tasks;
+use crate::queueable;
+use crate::queueable::QueueError;
+use crate::queueable::Queueable;
+use crate::queueable::Task;
+use crate::runnable::Runnable;
+use crate::sqlite_schema::fang_tasks;
+use crate::task::FangTaskState;
+use crate::FangError;
+use crate::EXPIRED_HEARTBEAT_ERROR;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::r2d2;
+use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::CustomizeConnection;
+use diesel::r2d2::PooledConnection;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::BigInt;
+use diesel::sql_types::Text;
+use diesel::sql_types::TimestamptzSqlite;
+use diesel::sqlite::SqliteConnection;
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+pub type SqlitePool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+pub type SqlitePoolConnection = PooledConnection<ConnectionManager<SqliteConnection>>;
+
+/// SQLite allows only one writer at a time, other connections wait for the lock up to this timeout
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+// SQLite locks the whole database while the statement is executed,
+// so the same task can't be claimed by two workers
+const FETCH_AND_TOUCH_TASKS_QUERY: &str = "UPDATE fang_tasks SET state = 'in_progress', heartbeat_at = ?2, updated_at = ?2 \
+    WHERE id IN (SELECT id FROM fang_tasks WHERE task_type = ?1 AND state IN ('new', 'retried') AND scheduled_at <= ?2 \
+    ORDER BY priority DESC, scheduled_at ASC, created_at ASC LIMIT ?3) RETURNING *";
+
+/// A row of the `fang_tasks` table in SQLite.
+///
+/// SQLite doesn't have uuid, jsonb and enum types so they are stored as text.
+#[derive(Queryable, QueryableByName, Insertable, Debug, Clone)]
+#[diesel(table_name = fang_tasks)]
+struct SqliteTask {
+    id: String,
+    metadata: String,
+    error_message: Option<String>,
+    state: String,
+    task_type: String,
+    uniq_hash: Option<String>,
+    retries: i32,
+    scheduled_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    heartbeat_at: Option<DateTime<Utc>>,
+    priority: i16,
+    error_details: Option<String>,
+}
+
+impl TryFrom<SqliteTask> for Task {
+    type Error = QueueError;
+
+    fn try_from(task: SqliteTask) -> Result<Self, Self::Error> {
+        Ok(Task::builder()
+            .id(Uuid::parse_str(&task.id).map_err(deserialization_error)?)
+            .metadata(
+                serde_json::from_str::<serde_json::Value>(&task.metadata)
+                    .map_err(deserialization_error)?,
+            )
+            .error_message(task.error_message)
+            .state(FangTaskState::from_name(&task.state).ok_or_else(|| {
+                deserialization_error(format!("Unknown task state {}", task.state))
+            })?)
+            .task_type(task.task_type)
+            .uniq_hash(task.uniq_hash)
+            .retries(task.retries)
+            .scheduled_at(task.scheduled_at)
+            .created_at(task.created_at)
+            .updated_at(task.updated_at)
+            .heartbeat_at(task.heartbeat_at)
+            .priority(task.priority)
+            .error_details(
+                task.error_details
+                    .map(|details| serde_json::from_str::<serde_json::Value>(&details))
+                    .transpose()
+                    .map_err(deserialization_error)?,
+            )
+            .build())
+    }
+}
+
+fn deserialization_error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> QueueError {
+    QueueError::DieselError(DieselError::DeserializationError(error.into()))
+}
+
+fn to_tasks(tasks: Vec<SqliteTask>) -> Result<Vec<Task>, QueueError> {
+    tasks.into_iter().map(Task::try_from).collect()
+}
+
+/// Sets options that every connection to the SQLite database needs
+#[derive(Debug, Clone, Copy)]
+struct SqliteConnectionOptions;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for SqliteConnectionOptions {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        connection
+            .batch_execute(&format!(
+                "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL;",
+                BUSY_TIMEOUT_MS
+            ))
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// A queue that uses a SQLite database as a storage.
+///
+/// The `fang_tasks` table has to be created with the migrations from the `sqlite_migrations` directory.
+/// Create the connection pool with [`SqliteQueue::connection_pool`], it configures connections
+/// to wait for the database lock instead of failing when several workers write at the same time.
+///
+///    ```rust
+///         let pool = SqliteQueue::connection_pool("fang.sqlite3", 3)?;
+///
+///         let queue = SqliteQueue::builder().connection_pool(pool).build();
+///     ```
+///
+#[derive(Clone, TypedBuilder)]
+pub struct SqliteQueue {
+    #[builder(setter(into))]
+    pub connection_pool: SqlitePool,
+}
+
+impl Queueable for SqliteQueue {
+    fn fetch_and_touch_task(&self, task_type: String) -> Result<Option<Task>, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::fetch_and_touch_query(&mut connection, task_type)
+    }
+
+    fn fetch_and_touch_tasks(
+        &self,
+        task_type: String,
+        limit: u32,
+    ) -> Result<Vec<Task>, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::fetch_and_touch_tasks_query(&mut connection, task_type, limit)
+    }
+
+    fn insert_task(&self, params: &dyn Runnable) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::insert_query(&mut connection, params, Utc::now())
+    }
+
+    fn insert_tasks(&self, tasks: &[&dyn Runnable]) -> Result<Vec<Task>, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::insert_tasks_query(&mut connection, tasks)
+    }
+
+    fn schedule_task(&self, params: &dyn Runnable) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::schedule_task_query(&mut connection, params)
+    }
+
+    fn remove_all_scheduled_tasks(&self) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::remove_all_scheduled_tasks_query(&mut connection)
+    }
+
+    fn remove_all_tasks(&self) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::remove_all_tasks_query(&mut connection)
+    }
+
+    fn remove_tasks_of_type(&self, task_type: &str) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::remove_tasks_of_type_query(&mut connection, task_type)
+    }
+
+    fn remove_task(&self, id: Uuid) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::remove_task_query(&mut connection, id)
+    }
+
+    fn remove_task_by_metadata(&self, task: &dyn Runnable) -> Result<usize, QueueError> {
+        if task.uniq() {
+            let mut connection = self.get_connection()?;
+
+            Self::remove_task_by_metadata_query(&mut connection, task)
+        } else {
+            Err(QueueError::TaskNotUniqError)
+        }
+    }
+
+    fn update_task_state(&self, task: &Task, state: FangTaskState) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::update_task_state_query(&mut connection, task, state)
+    }
+
+    fn fail_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::fail_task_query(
+            &mut connection,
+            task,
+            &error.description,
+            error.details.as_ref(),
+        )
+    }
+
+    fn dead_letter_task(&self, task: &Task, error: &FangError) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::dead_letter_task_query(
+            &mut connection,
+            task,
+            &error.description,
+            error.details.as_ref(),
+        )
+    }
+
+    fn find_task_by_id(&self, id: Uuid) -> Option<Task> {
+        let mut connection = self.get_connection().unwrap();
+
+        Self::find_task_by_id_query(&mut connection, id)
+    }
+
+    fn schedule_retry(
+        &self,
+        task: &Task,
+        backoff_seconds: u32,
+        error: &FangError,
+    ) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::schedule_retry_query(
+            &mut connection,
+            task,
+            backoff_seconds,
+            &error.description,
+            error.details.as_ref(),
+        )
+    }
+
+    fn heartbeat_task(&self, task: &Task) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::heartbeat_task_query(&mut connection, task)
+    }
+
+    fn reap_expired_tasks(
+        &self,
+        heartbeat_timeout: std::time::Duration,
+    ) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::reap_expired_tasks_query(&mut connection, heartbeat_timeout)
+    }
+
+    fn retry_failed_task(&self, id: Uuid) -> Result<Task, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::retry_failed_task_query(&mut connection, id)
+    }
+
+    fn retry_failed_tasks_of_type(&self, task_type: &str) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::retry_failed_tasks_of_type_query(&mut connection, task_type)
+    }
+
+    fn discard_dead_tasks(&self, older_than: std::time::Duration) -> Result<usize, QueueError> {
+        let mut connection = self.get_connection()?;
+
+        Self::discard_dead_tasks_query(&mut connection, older_than)
+    }
+}
+
+impl SqliteQueue {
+    /// Create a connection pool to the SQLite database at `database_url`.
+    ///
+    /// Connections wait up to 5 seconds for the database lock and use the WAL journal mode,
+    /// so workers can read while a task is being enqueued.
+    /// Note that every connection to `:memory:` opens a separate database.
+    pub fn connection_pool(database_url: &str, pool_size: u32) -> Result<SqlitePool, QueueError> {
+        let manager = ConnectionManager::<SqliteConnection>::new(database_url);
+
+        Ok(r2d2::Pool::builder()
+            .max_size(pool_size)
+            .connection_customizer(Box::new(SqliteConnectionOptions))
+            .build(manager)?)
+    }
+
+    /// Get a connection from the pool
+    pub fn get_connection(&self) -> Result<SqlitePoolConnection, QueueError> {
+        let result = self.connection_pool.get();
+
+        if let Err(err) = result {
+            log::error!("Failed to get a db connection {:?}", err);
+            return Err(QueueError::PoolError(err));
+        }
+
+        Ok(result.unwrap())
+    }
+
+    pub fn schedule_task_query(
+        connection: &mut SqliteConnection,
+        params: &dyn Runnable,
+    ) -> Result<Task, QueueError> {
+        let scheduled_at = queueable::calculate_scheduled_at(params)?;
+
+        Self::insert_query(connection, params, scheduled_at)
+    }
+
+    pub fn insert_query(
+        connection: &mut SqliteConnection,
+        params: &dyn Runnable,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<Task, QueueError> {
+        // the write lock is taken right away, so a unique task can't be inserted twice
+        connection.immediate_transaction::<Task, QueueError, _>(|conn| {
+            Self::insert_task_query(conn, params, scheduled_at)
+        })
+    }
+
+    pub fn insert_tasks_query(
+        connection: &mut SqliteConnection,
+        tasks: &[&dyn Runnable],
+    ) -> Result<Vec<Task>, QueueError> {
+        let scheduled_at = Utc::now();
+
+        connection.immediate_transaction::<Vec<Task>, QueueError, _>(|conn| {
+            tasks
+                .iter()
+                .map(|task| Self::insert_task_query(conn, *task, scheduled_at))
+                .collect()
+        })
+    }
+
+    fn insert_task_query(
+        connection: &mut SqliteConnection,
+        params: &dyn Runnable,
+        scheduled_at: DateTime<Utc>,
+    ) -> Result<Task, QueueError> {
+        let metadata = serde_json::to_value(params).unwrap();
+
+        let uniq_hash = if params.uniq() {
+            let uniq_hash = new_tasks::calculate_hash(metadata.to_string());
+
+            if let Some(task) = Self::find_task_by_uniq_hash_query(connection, &uniq_hash) {
+                return Ok(task);
+            }
+
+            Some(uniq_hash)
+        } else {
+            None
+        };
+
+        let now = Utc::now();
+
+        let new_task = SqliteTask {
+            id: Uuid::new_v4().to_string(),
+            metadata: metadata.to_string(),
+            error_message: None,
+            state: FangTaskState::New.as_str().to_string(),
+            task_type: params.task_type(),
+            uniq_hash,
+            retries: 0,
+            scheduled_at,
+            created_at: now,
+            updated_at: now,
+            heartbeat_at: None,
+            priority: params.priority(),
+            error_details: None,
+        };
+
+        diesel::insert_into(fang_tasks::table)
+            .values(new_task)
+            .get_result::<SqliteTask>(connection)?
+            .try_into()
+    }
+
+    pub fn fetch_and_touch_query(
+        connection: &mut SqliteConnection,
+        task_type: String,
+    ) -> Result<Option<Task>, QueueError> {
+        Ok(Self::fetch_and_touch_tasks_query(connection, task_type, 1)?.pop())
+    }
+
+    pub fn fetch_and_touch_tasks_query(
+        connection: &mut SqliteConnection,
+        task_type: String,
+        limit: u32,
+    ) -> Result<Vec<Task>, QueueError> {
+        let now = Utc::now();
+
+        let tasks = diesel::sql_query(FETCH_AND_TOUCH_TASKS_QUERY)
+            .bind::<Text, _>(task_type)
+            .bind::<TimestamptzSqlite, _>(now)
+            .bind::<BigInt, _>(limit as i64)
+            .load::<SqliteTask>(connection)?;
+
+        let mut tasks = to_tasks(tasks)?;
+
+        // `RETURNING` doesn't preserve the order of the subquery
+        tasks.sort_by(Task::fetch_order);
+
+        Ok(tasks)
+    }
+
+    pub fn heartbeat_task_query(
+        connection: &mut SqliteConnection,
+        task: &Task,
+    ) -> Result<usize, QueueError> {
+        let query = fang_tasks::table
+            .filter(fang_tasks::id.eq(task.id.to_string()))
+            .filter(fang_tasks::state.eq(FangTaskState::InProgress.as_str()))
+            .filter(fang_tasks::updated_at.eq(task.updated_at));
+
+        Ok(diesel::update(query)
+            .set(fang_tasks::heartbeat_at.eq(Utc::now()))
+            .execute(connection)?)
+    }
+
+    pub fn reap_expired_tasks_query(
+        connection: &mut SqliteConnection,
+        heartbeat_timeout: std::time::Duration,
+    ) -> Result<usize, QueueError> {
+        let heartbeat_timeout =
+            Duration::from_std(heartbeat_timeout).map_err(|_| QueueError::TimeError)?;
+        let expired_at = Utc::now() - heartbeat_timeout;
+
+        connection.immediate_transaction::<usize, QueueError, _>(|conn| {
+            let expired_tasks = fang_tasks::table
+                .filter(fang_tasks::state.eq(FangTaskState::InProgress.as_str()))
+                .filter(
+                    fang_tasks::heartbeat_at
+                        .lt(expired_at)
+                        .or(fang_tasks::heartbeat_at
+                            .is_null()
+                            .and(fang_tasks::updated_at.lt(expired_at))),
+                )
+                .load::<SqliteTask>(conn)?;
+
+            let expired_tasks = to_tasks(expired_tasks)?;
+
+            for task in &expired_tasks {
+                match serde_json::from_value::<Box<dyn Runnable>>(task.metadata.clone()) {
+                    Ok(runnable) if task.retries < runnable.max_retries() => {
+                        let backoff_seconds = runnable.backoff(task.retries as u32);
+
+                        Self::schedule_retry_query(
+                            conn,
+                            task,
+                            backoff_seconds,
+                            EXPIRED_HEARTBEAT_ERROR,
+                            None,
+                        )?;
+                    }
+                    Ok(_) => {
+                        Self::dead_letter_task_query(conn, task, EXPIRED_HEARTBEAT_ERROR, None)?;
+                    }
+                    Err(_) => {
+                        Self::fail_task_query(conn, task, EXPIRED_HEARTBEAT_ERROR, None)?;
+                    }
+                }
+            }
+
+            Ok(expired_tasks.len())
+        })
+    }
+
+    pub fn find_task_by_id_query(connection: &mut SqliteConnection, id: Uuid) -> Option<Task> {
+        fang_tasks::table
+            .filter(fang_tasks::id.eq(id.to_string()))
+            .first::<SqliteTask>(connection)
+            .ok()
+            .and_then(|task| task.try_into().ok())
+    }
+
+    pub fn remove_all_tasks_query(connection: &mut SqliteConnection) -> Result<usize, QueueError> {
+        Ok(diesel::delete(fang_tasks::table).execute(connection)?)
+    }
+
+    pub fn remove_all_scheduled_tasks_query(
+        connection: &mut SqliteConnection,
+    ) -> Result<usize, QueueError> {
+        let query = fang_tasks::table.filter(fang_tasks::scheduled_at.gt(Utc::now()));
+
+        Ok(diesel::delete(query).execute(connection)?)
+    }
+
+    pub fn remove_tasks_of_type_query(
+        connection: &mut SqliteConnection,
+        task_type: &str,
+    ) -> Result<usize, QueueError> {
+        let query = fang_tasks::table.filter(fang_tasks::task_type.eq(task_type));
+
+        Ok(diesel::delete(query).execute(connection)?)
+    }
+
+    pub fn remove_task_by_metadata_query(
+        connection: &mut SqliteConnection,
+        task: &dyn Runnable,
+    ) -> Result<usize, QueueError> {
+        let metadata = serde_json::to_value(task).unwrap();
+
+        let uniq_hash = new_tasks::calculate_hash(metadata.to_string());
+
+        let query = fang_tasks::table.filter(fang_tasks::uniq_hash.eq(uniq_hash));
+
+        Ok(diesel::delete(query).execute(connection)?)
+    }
+
+    pub fn remove_task_query(
+        connection: &mut SqliteConnection,
+        id: Uuid,
+    ) -> Result<usize, QueueError> {
+        let query = fang_tasks::table.filter(fang_tasks::id.eq(id.to_string()));
+
+        Ok(diesel::delete(query).execute(connection)?)
+    }
+
+    pub fn update_task_state_query(
+        connection: &mut SqliteConnection,
+        task: &Task,
+        state: FangTaskState,
+    ) -> Result<Task, QueueError> {
+        diesel::update(fang_tasks::table.filter(fang_tasks::id.eq(task.id.to_string())))
+            .set((
+                fang_tasks::state.eq(state.as_str()),
+                fang_tasks::updated_at.eq(Utc::now()),
+            ))
+            .get_result::<SqliteTask>(connection)?
+            .try_into()
+    }
+
+    pub fn fail_task_query(
+        connection: &mut SqliteConnection,
+        task: &Task,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Result<Task, QueueError> {
+        Self::end_task_query(
+            connection,
+            task,
+            FangTaskState::Failed,
+            error_message,
+            error_details,
+        )
+    }
+
+    pub fn dead_letter_task_query(
+        connection: &mut SqliteConnection,
+        task: &Task,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Result<Task, QueueError> {
+        Self::end_task_query(
+            connection,
+            task,
+            FangTaskState::Dead,
+            error_message,
+            error_details,
+        )
+    }
+
+    /// Move a task to the final `state` of an unsuccessful task, `Failed` or `Dead`
+    fn end_task_query(
+        connection: &mut SqliteConnection,
+        task: &Task,
+        state: FangTaskState,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Result<Task, QueueError> {
+        diesel::update(fang_tasks::table.filter(fang_tasks::id.eq(task.id.to_string())))
+            .set((
+                fang_tasks::state.eq(state.as_str()),
+                fang_tasks::error_message.eq(error_message),
+                fang_tasks::error_details.eq(error_details.map(|details| details.to_string())),
+                fang_tasks::updated_at.eq(Utc::now()),
+            ))
+            .get_result::<SqliteTask>(connection)?
+            .try_into()
+    }
+
+    pub fn retry_failed_task_query(
+        connection: &mut SqliteConnection,
+        id: Uuid,
+    ) -> Result<Task, QueueError> {
+        let now = Utc::now();
+
+        let query = fang_tasks::table
+            .filter(fang_tasks::id.eq(id.to_string()))
+            .filter(fang_tasks::state.eq_any(vec![
+                FangTaskState::Failed.as_str(),
+                FangTaskState::Dead.as_str(),
+            ]));
+
+        diesel::update(query)
+            .set((
+                fang_tasks::state.eq(FangTaskState::New.as_str()),
+                fang_tasks::retries.eq(0),
+                fang_tasks::scheduled_at.eq(now),
+                fang_tasks::updated_at.eq(now),
+            ))
+            .get_result::<SqliteTask>(connection)?
+            .try_into()
+    }
+
+    pub fn retry_failed_tasks_of_type_query(
+        connection: &mut SqliteConnection,
+        task_type: &str,
+    ) -> Result<usize, QueueError> {
+        let now = Utc::now();
+
+        let query = fang_tasks::table
+            .filter(fang_tasks::task_type.eq(task_type))
+            .filter(fang_tasks::state.eq_any(vec![
+                FangTaskState::Failed.as_str(),
+                FangTaskState::Dead.as_str(),
+            ]));
+
+        Ok(diesel::update(query)
+            .set((
+                fang_tasks::state.eq(FangTaskState::New.as_str()),
+                fang_tasks::retries.eq(0),
+                fang_tasks::scheduled_at.eq(now),
+                fang_tasks::updated_at.eq(now),
+            ))
+            .execute(connection)?)
+    }
+
+    pub fn discard_dead_tasks_query(
+        connection: &mut SqliteConnection,
+        older_than: std::time::Duration,
+    ) -> Result<usize, QueueError> {
+        let older_than = Duration::from_std(older_than).map_err(|_| QueueError::TimeError)?;
+        let updated_before = Utc::now() - older_than;
+
+        let query = fang_tasks::table
+            .filter(fang_tasks::state.eq(FangTaskState::Dead.as_str()))
+            .filter(fang_tasks::updated_at.lt(updated_before));
+
+        Ok(diesel::delete(query).execute(connection)?)
+    }
+
+    pub fn schedule_retry_query(
+        connection: &mut SqliteConnection,
+        task: &Task,
+        backoff_seconds: u32,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) -> Result<Task, QueueError> {
+        let now = Utc::now();
+        let scheduled_at = now + Duration::seconds(backoff_seconds as i64);
+
+        diesel::update(fang_tasks::table.filter(fang_tasks::id.eq(task.id.to_string())))
+            .set((
+                fang_tasks::state.eq(FangTaskState::Retried.as_str()),
+                fang_tasks::error_message.eq(error_message),
+                fang_tasks::error_details.eq(error_details.map(|details| details.to_string())),
+                fang_tasks::retries.eq(task.retries + 1),
+                fang_tasks::scheduled_at.eq(scheduled_at),
+                fang_tasks::updated_at.eq(now),
+            ))
+            .get_result::<SqliteTask>(connection)?
+            .try_into()
+    }
+
+    fn find_task_by_uniq_hash_query(
+        connection: &mut SqliteConnection,
+        uniq_hash: &str,
+    ) -> Option<Task> {
+        fang_tasks::table
+            .filter(fang_tasks::uniq_hash.eq(uniq_hash))
+            .filter(fang_tasks::state.eq_any(vec![
+                FangTaskState::New.as_str(),
+                FangTaskState::Retried.as_str(),
+            ]))
+            .first::<SqliteTask>(connection)
+            .ok()
+            .and_then(|task| task.try_into().ok())
+    }
+}
+
+#[cfg(test)]
+mod sqlite_queue_tests {
+    use super::SqliteQueue;
+    use crate::chrono::SubsecRound;
+    use crate::queueable::QueueError;
+    use crate::queueable::Queueable;
+    use crate::runnable::Runnable;
+    use crate::runnable::COMMON_TYPE;
+    use crate::task::FangTaskState;
+    use crate::typetag;
+    use crate::worker::Worker;
+    use crate::FangError;
+    use crate::RetentionMode;
+    use crate::Scheduled;
+    use crate::TaskContext;
+    use crate::EXPIRED_HEARTBEAT_ERROR;
+    use chrono::DateTime;
+    use chrono::Duration;
+    use chrono::Utc;
+    use diesel::connection::SimpleConnection;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashSet;
+    use uuid::Uuid;
+
+    const CREATE_FANG_TASKS: &str =
+        include_str!("../../sqlite_migrations/2026-10-15-110000_create_fang_tasks/up.sql");
+
+    const ADD_ERROR_DETAILS: &str = include_str!(
+        "../../sqlite_migrations/2026-10-15-130000_add_error_details_to_fang_tasks/up.sql"
+    );
+
+    const ADD_DEAD_STATE: &str = include_str!(
+        "../../sqlite_migrations/2026-10-15-150000_add_dead_state_to_fang_tasks/up.sql"
+    );
+
+    #[derive(Serialize, Deserialize)]
+    struct SqliteTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for SqliteTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct SqliteUrgentTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for SqliteUrgentTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn priority(&self) -> i16 {
+            10
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct SqliteUniqTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for SqliteUniqTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn uniq(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct SqliteScheduledTask {
+        pub number: u16,
+        pub datetime: String,
+    }
+
+    #[typetag::serde]
+    impl Runnable for SqliteScheduledTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Ok(())
+        }
+
+        fn cron(&self) -> Option<Scheduled> {
+            let datetime = self.datetime.parse::<DateTime<Utc>>().ok()?;
+            Some(Scheduled::ScheduleOnce(datetime))
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct SqliteFailingTask {
+        pub number: u16,
+    }
+
+    #[typetag::serde]
+    impl Runnable for SqliteFailingTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            Err(FangError::retryable("Failed"))
+        }
+
+        fn task_type(&self) -> String {
+            "sqlite_failing".to_string()
+        }
+
+        fn max_retries(&self) -> i32 {
+            1
+        }
+    }
+
+    #[test]
+    fn insert_task_test() {
+        let queue = queue(":memory:", 1);
+
+        let task = queue.insert_task(&SqliteTask { number: 1 }).unwrap();
+
+        assert_eq!(Some(1), task.metadata["number"].as_u64());
+        assert_eq!(Some("SqliteTask"), task.metadata["type"].as_str());
+        assert_eq!(FangTaskState::New, task.state);
+        assert_eq!(COMMON_TYPE, task.task_type);
+        assert_eq!(None, task.uniq_hash);
+        assert_eq!(0, task.retries);
+        assert_eq!(Some(task.clone()), queue.find_task_by_id(task.id));
+    }
+
+    #[test]
+    fn insert_task_does_not_duplicate_uniq_tasks() {
+        let queue = queue(":memory:", 1);
+
+        let task1 = queue.insert_task(&SqliteUniqTask { number: 1 }).unwrap();
+        let task2 = queue.insert_task(&SqliteUniqTask { number: 1 }).unwrap();
+        let tasks = queue
+            .insert_tasks(&[
+                &SqliteUniqTask { number: 1 },
+                &SqliteUniqTask { number: 2 },
+                &SqliteUniqTask { number: 2 },
+                &SqliteTask { number: 2 },
+            ])
+            .unwrap();
+
+        assert!(task1.uniq_hash.is_some());
+        assert_eq!(task1.id, task2.id);
+        assert_eq!(task1.id, tasks[0].id);
+        assert_eq!(tasks[1].id, tasks[2].id);
+        assert_eq!(Some("SqliteTask"), tasks[3].metadata["type"].as_str());
+
+        queue
+            .update_task_state(&task1, FangTaskState::Finished)
+            .unwrap();
+
+        let task3 = queue.insert_task(&SqliteUniqTask { number: 1 }).unwrap();
+
+        assert_ne!(task1.id, task3.id);
+    }
+
+    #[test]
+    fn fetch_and_touch_respects_type_and_priority() {
+        let queue = queue(":memory:", 1);
+
+        let task1 = queue.insert_task(&SqliteTask { number: 1 }).unwrap();
+        let task2 = queue.insert_task(&SqliteUrgentTask { number: 2 }).unwrap();
+        queue.insert_task(&SqliteFailingTask { number: 3 }).unwrap();
+
+        let fetched_task = queue
+            .fetch_and_touch_task(COMMON_TYPE.to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(task2.id, fetched_task.id);
+        assert_eq!(FangTaskState::InProgress, fetched_task.state);
+        assert!(fetched_task.heartbeat_at.is_some());
+
+        let tasks = queue
+            .fetch_and_touch_tasks(COMMON_TYPE.to_string(), 2)
+            .unwrap();
+
+        assert_eq!(1, tasks.len());
+        assert_eq!(task1.id, tasks[0].id);
+        assert_eq!(
+            None,
+            queue.fetch_and_touch_task(COMMON_TYPE.to_string()).unwrap()
+        );
+    }
+
+    #[test]
+    fn schedule_task_test() {
+        let queue = queue(":memory:", 1);
+
+        let datetime = (Utc::now() + Duration::seconds(7)).round_subsecs(0);
+
+        let task = queue
+            .schedule_task(&SqliteScheduledTask {
+                number: 1,
+                datetime: datetime.to_string(),
+            })
+            .unwrap();
+
+        assert_eq!(datetime, task.scheduled_at);
+        assert_eq!(
+            None,
+            queue.fetch_and_touch_task(COMMON_TYPE.to_string()).unwrap()
+        );
+        assert_eq!(1, queue.remove_all_scheduled_tasks().unwrap());
+        assert_eq!(0, queue.remove_all_tasks().unwrap());
+    }
+
+    #[test]
+    fn schedule_retry_test() {
+        let queue = queue(":memory:", 1);
+
+        queue.insert_task(&SqliteTask { number: 1 }).unwrap();
+
+        let task = queue
+            .fetch_and_touch_task(COMMON_TYPE.to_string())
+            .unwrap()
+            .unwrap();
+
+        let retried_task = queue
+            .schedule_retry(
+                &task,
+                60,
+                &FangError::retryable("Failed").with_details(serde_json::json!({"code": 42})),
+            )
+            .unwrap();
+
+        assert_eq!(FangTaskState::Retried, retried_task.state);
+        assert_eq!(1, retried_task.retries);
+        assert_eq!(Some("Failed".to_string()), retried_task.error_message);
+        assert_eq!(
+            Some(serde_json::json!({"code": 42})),
+            retried_task.error_details
+        );
+        assert!(retried_task.scheduled_at > Utc::now() + Duration::seconds(50));
+        assert_eq!(
+            None,
+            queue.fetch_and_touch_task(COMMON_TYPE.to_string()).unwrap()
+        );
+
+        let failed_task = queue
+            .fail_task(&retried_task, &FangError::retryable("Some error"))
+            .unwrap();
+
+        assert_eq!(FangTaskState::Failed, failed_task.state);
+        assert_eq!(Some("Some error".to_string()), failed_task.error_message);
+        assert_eq!(None, failed_task.error_details);
+    }
+
+    #[test]
+    fn remove_tasks_test() {
+        let queue = queue(":memory:", 1);
+
+        let task = queue.insert_task(&SqliteTask { number: 1 }).unwrap();
+        queue.insert_task(&SqliteUniqTask { number: 1 }).unwrap();
+        queue.insert_task(&SqliteFailingTask { number: 1 }).unwrap();
+
+        assert_eq!(1, queue.remove_task(task.id).unwrap());
+        assert_eq!(0, queue.remove_task(task.id).unwrap());
+        assert!(matches!(
+            queue.remove_task_by_metadata(&SqliteTask { number: 1 }),
+            Err(QueueError::TaskNotUniqError)
+        ));
+        assert_eq!(
+            1,
+            queue
+                .remove_task_by_metadata(&SqliteUniqTask { number: 1 })
+                .unwrap()
+        );
+        assert_eq!(1, queue.remove_tasks_of_type("sqlite_failing").unwrap());
+        assert_eq!(None, queue.find_task_by_id(task.id));
+        assert!(queue.update_task_state(&task, FangTaskState::New).is_err());
+    }
+
+    #[test]
+    fn reap_expired_tasks_test() {
+        let queue = queue(":memory:", 1);
+
+        queue.insert_task(&SqliteTask { number: 1 }).unwrap();
+
+        let task = queue
+            .fetch_and_touch_task(COMMON_TYPE.to_string())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(1, queue.heartbeat_task(&task).unwrap());
+        assert_eq!(
+            0,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_secs(60))
+                .unwrap()
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        assert_eq!(
+            1,
+            queue
+                .reap_expired_tasks(std::time::Duration::from_millis(1))
+                .unwrap()
+        );
+
+        let reaped_task = queue.find_task_by_id(task.id).unwrap();
+
+        assert_eq!(FangTaskState::Retried, reaped_task.state);
+        assert_eq!(1, reaped_task.retries);
+        assert_eq!(
+            Some(EXPIRED_HEARTBEAT_ERROR.to_string()),
+            reaped_task.error_message
+        );
+        assert_eq!(0, queue.heartbeat_task(&reaped_task).unwrap());
+    }
+
+    #[test]
+    fn task_attempts_are_unsupported() {
+        let queue = queue(":memory:", 1);
+
+        let task = queue.insert_task(&SqliteFailingTask { number: 1 }).unwrap();
+
+        assert!(matches!(
+            queue.find_task_attempts(task.id),
+            Err(QueueError::Unsupported("find_task_attempts"))
+        ));
+    }
+
+    #[test]
+    fn retry_failed_tasks_and_discard_dead_tasks_test() {
+        let queue = queue(":memory:", 1);
+
+        let task1 = queue.insert_task(&SqliteFailingTask { number: 1 }).unwrap();
+        let task2 = queue.insert_task(&SqliteFailingTask { number: 2 }).unwrap();
+        let task3 = queue.insert_task(&SqliteFailingTask { number: 3 }).unwrap();
+
+        let error = FangError::permanent("Failed");
+        queue.fail_task(&task1, &error).unwrap();
+        queue.dead_letter_task(&task2, &error).unwrap();
+        queue.fail_task(&task3, &error).unwrap();
+
+        let retried_task = queue.retry_failed_task(task1.id).unwrap();
+
+        assert_eq!(FangTaskState::New, retried_task.state);
+        assert_eq!(0, retried_task.retries);
+        assert_eq!(Some("Failed".to_string()), retried_task.error_message);
+        assert!(queue.retry_failed_task(task1.id).is_err());
+
+        assert_eq!(
+            2,
+            queue.retry_failed_tasks_of_type("sqlite_failing").unwrap()
+        );
+
+        queue.dead_letter_task(&task3, &error).unwrap();
+
+        assert_eq!(
+            0,
+            queue
+                .discard_dead_tasks(std::time::Duration::from_secs(60))
+                .unwrap()
+        );
+
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        assert_eq!(
+            1,
+            queue
+                .discard_dead_tasks(std::time::Duration::from_millis(1))
+                .unwrap()
+        );
+        assert_eq!(None, queue.find_task_by_id(task3.id));
+        assert_eq!(
+            FangTaskState::New,
+            queue.find_task_by_id(task2.id).unwrap().state
+        );
+    }
+
+    #[test]
+    fn worker_executes_tasks() {
+        let queue = queue(":memory:", 1);
+
+        let task = queue.insert_task(&SqliteTask { number: 1 }).unwrap();
+        let failing_task = queue.insert_task(&SqliteFailingTask { number: 1 }).unwrap();
+
+        let mut worker = Worker::<SqliteQueue>::builder()
+            .queue(queue.clone())
+            .retention_mode(RetentionMode::KeepAll)
+            .build();
+
+        let mut failing_worker = Worker::<SqliteQueue>::builder()
+            .queue(queue.clone())
+            .task_type("sqlite_failing")
+            .retention_mode(RetentionMode::KeepAll)
+            .build();
+
+        worker.run_tasks_until_none().unwrap();
+        failing_worker.run_tasks_until_none().unwrap();
+
+        let task = queue.find_task_by_id(task.id).unwrap();
+        let failing_task = queue.find_task_by_id(failing_task.id).unwrap();
+
+        assert_eq!(FangTaskState::Finished, task.state);
+        assert_eq!(FangTaskState::Retried, failing_task.state);
+        assert_eq!(1, failing_task.retries);
+        assert_eq!(Some("Failed".to_string()), failing_task.error_message);
+    }
+
+    #[test]
+    fn concurrent_workers_do_not_fetch_the_same_task() {
+        let path = std::env::temp_dir().join(format!("fang_{}.sqlite3", Uuid::new_v4()));
+        let queue = queue(path.to_str().unwrap(), 4);
+
+        for number in 0..40 {
+            queue.insert_task(&SqliteTask { number }).unwrap();
+        }
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let queue = queue.clone();
+
+                std::thread::spawn(move || {
+                    let mut ids = Vec::new();
+
+                    while let Some(task) =
+                        queue.fetch_and_touch_task(COMMON_TYPE.to_string()).unwrap()
+                    {
+                        ids.push(task.id);
+                    }
+
+                    ids
+                })
+            })
+            .collect();
+
+        let ids: Vec<Uuid> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        let uniq_ids: HashSet<&Uuid> = ids.iter().collect();
+
+        drop(queue);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(40, ids.len());
+        assert_eq!(40, uniq_ids.len());
+    }
+
+    fn queue(database_url: &str, pool_size: u32) -> SqliteQueue {
+        let pool = SqliteQueue::connection_pool(database_url, pool_size).unwrap();
+
+        pool.get()
+            .unwrap()
+            .batch_execute(CREATE_FANG_TASKS)
+            .unwrap();
+        pool.get()
+            .unwrap()
+            .batch_execute(ADD_ERROR_DETAILS)
+            .unwrap();
+        pool.get().unwrap().batch_execute(ADD_DEAD_STATE).unwrap();
+
+        SqliteQueue::builder().connection_pool(pool).build()
+    }
+}
@@ -0,0 +1,17 @@
+diesel::table! {
+    fang_tasks (id) {
+        id -> Text,
+        metadata -> Text,
+        error_message -> Nullable<Text>,
+        state -> Text,
+        task_type -> Text,
+        uniq_hash -> Nullable<Text>,
+        retries -> Integer,
+        scheduled_at -> TimestamptzSqlite,
+        created_at -> TimestamptzSqlite,
+        updated_at -> TimestamptzSqlite,
+        heartbeat_at -> Nullable<TimestamptzSqlite>,
+        priority -> SmallInt,
+        error_details -> Nullable<Text>,
+    }
+}
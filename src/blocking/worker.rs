@@ -1,17 +1,35 @@
 #![allow(clippy::borrowed_box)]
 #![allow(clippy::unnecessary_unwrap)]
 
-use crate::fang_task_state::FangTaskState;
-use crate::queue::Queueable;
-use crate::queue::Task;
+use crate::queueable::QueueError;
+use crate::queueable::Queueable;
+use crate::queueable::Task;
+use crate::queueable::TaskAttempt;
+use crate::queueable::TaskListener;
 use crate::runnable::Runnable;
 use crate::runnable::COMMON_TYPE;
+use crate::task::FangTaskState;
+use crate::Extensions;
 use crate::FangError;
+use crate::FangErrorKind;
 use crate::Scheduled::*;
-use crate::{RetentionMode, SleepParams};
+use crate::TaskContext;
+use crate::{RetentionMode, SleepParams, DEFAULT_HEARTBEAT_INTERVAL};
+use chrono::DateTime;
+use chrono::Utc;
 use log::error;
+use log::warn;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
 use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
 use typed_builder::TypedBuilder;
+use uuid::Uuid;
 
 /// A executioner of tasks, it executes tasks only of one given task_type, it sleeps when they are
 /// not tasks to be executed.
@@ -28,6 +46,117 @@ where
     pub sleep_params: SleepParams,
     #[builder(default, setter(into))]
     pub retention_mode: RetentionMode,
+    /// the worker stops fetching new tasks once this token is cancelled
+    #[builder(default, setter(into))]
+    pub shutdown_token: ShutdownToken,
+    /// the period between heartbeats that are sent while a task is executed
+    #[builder(default=DEFAULT_HEARTBEAT_INTERVAL, setter(into))]
+    pub heartbeat_interval: Duration,
+    /// the shared state that is passed to every executed task in its `TaskContext`
+    #[builder(default, setter(into))]
+    pub extensions: Extensions,
+    /// the id of the worker in the attempts of tasks it executes, a random UUID by default
+    #[builder(default=Uuid::new_v4().to_string(), setter(into))]
+    pub worker_id: String,
+    /// saves every execution of a task with `Queueable::record_task_attempt`
+    #[builder(default, setter(into))]
+    pub record_attempts: bool,
+    /// the listener of notifications about new tasks, the workers of a pool share one listener.
+    /// The worker starts its own listener if it's not set
+    #[builder(default, setter(into))]
+    task_listener: Option<TaskListener>,
+}
+
+/// How often a sleeping worker checks if it was notified about new tasks
+const NOTIFICATIONS_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A token that is used to ask workers to stop.
+///
+/// Clones of the token share the same state, cancelling one of them cancels all of them.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownToken {
+    cancelled: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ShutdownToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token and wake up all threads waiting on it
+    pub fn cancel(&self) {
+        let (lock, condvar) = &*self.cancelled;
+
+        *lock.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    /// Check if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        let (lock, _condvar) = &*self.cancelled;
+
+        *lock.lock().unwrap()
+    }
+
+    /// Block the current thread until the token is cancelled or the `timeout` expires.
+    /// Returns true if the token was cancelled
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let (lock, condvar) = &*self.cancelled;
+
+        let guard = lock.lock().unwrap();
+        let (cancelled, _timeout_result) = condvar
+            .wait_timeout_while(guard, timeout, |cancelled| !*cancelled)
+            .unwrap();
+
+        *cancelled
+    }
+}
+
+/// Sends heartbeats of a task from a separate thread while the task is executed.
+/// Heartbeats are stopped when it's dropped.
+struct Heartbeat {
+    stop_token: ShutdownToken,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+    fn start<BQueue>(queue: BQueue, task: Task, heartbeat_interval: Duration) -> Self
+    where
+        BQueue: Queueable + Clone + Sync + Send + 'static,
+    {
+        let stop_token = ShutdownToken::new();
+        let thread_stop_token = stop_token.clone();
+
+        let join_handle = thread::spawn(move || {
+            while !thread_stop_token.wait_timeout(heartbeat_interval) {
+                match queue.heartbeat_task(&task) {
+                    Ok(0) | Err(QueueError::Unsupported(_)) => return,
+                    Ok(_) => {}
+                    Err(error) => {
+                        error!(
+                            "Failed to send a heartbeat for the task {} {:?}",
+                            task.id, error
+                        );
+                    }
+                }
+            }
+        });
+
+        Self {
+            stop_token,
+            join_handle: Some(join_handle),
+        }
+    }
+}
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.stop_token.cancel();
+
+        if let Some(join_handle) = self.join_handle.take() {
+            join_handle.join().ok();
+        }
+    }
 }
 
 impl<BQueue> Worker<BQueue>
@@ -35,31 +164,174 @@ where
     BQueue: Queueable + Clone + Sync + Send + 'static,
 {
     pub fn run(&self, task: Task) {
-        let runnable: Box<dyn Runnable> = serde_json::from_value(task.metadata.clone()).unwrap();
-        let result = runnable.run(&self.queue);
+        let runnable: Box<dyn Runnable> = match serde_json::from_value(task.metadata.clone()) {
+            Ok(runnable) => runnable,
+            Err(error) => return self.fail_undeserializable_task(&task, error),
+        };
+
+        let context = TaskContext {
+            extensions: self.extensions.clone(),
+            ..TaskContext::from(&task)
+        };
+
+        let started_at = Utc::now();
+        let heartbeat = Heartbeat::start(self.queue.clone(), task.clone(), self.heartbeat_interval);
+        let result = match runnable.timeout() {
+            Some(timeout) => self.run_with_timeout(&task, context, timeout),
+            None => runnable.run(&self.queue, &context),
+        };
+        drop(heartbeat);
+
+        if !self.owns_task(&task) {
+            return;
+        }
 
         match result {
-            Ok(_) => self.finalize_task(task, &result),
+            Ok(_) => {
+                self.record_final_attempt(&task, started_at, &result);
+                self.finalize_task(task, &result)
+            }
             Err(ref error) => {
-                if task.retries < runnable.max_retries() {
-                    let backoff_seconds = runnable.backoff(task.retries as u32);
-
-                    self.queue
-                        .schedule_retry(&task, backoff_seconds, &error.description)
-                        .expect("Failed to retry");
-                } else {
-                    self.finalize_task(task, &result);
+                let backoff_seconds =
+                    error.retry_backoff(task.retries, runnable.max_retries(), || {
+                        runnable.backoff(task.retries as u32)
+                    });
+
+                match backoff_seconds {
+                    Some(backoff_seconds) => {
+                        self.record_attempt(&task, started_at, FangTaskState::Retried, &result);
+                        self.queue
+                            .schedule_retry(&task, backoff_seconds, error)
+                            .expect("Failed to retry");
+                    }
+                    None if error.kind == FangErrorKind::Permanent
+                        && self.retention_mode.keeps_failed() =>
+                    {
+                        self.record_final_attempt(&task, started_at, &result);
+                        self.finalize_task(task, &result)
+                    }
+                    // the task used up its retries or failed permanently and isn't kept as failed,
+                    // it's kept regardless of the retention mode
+                    None => {
+                        self.record_attempt(&task, started_at, FangTaskState::Dead, &result);
+                        self.queue
+                            .dead_letter_task(&task, error)
+                            .expect("Failed to move the task to the dead letters");
+                    }
                 }
             }
         }
     }
 
+    /// Checks that the task was not returned to the queue by `reap_expired_tasks` while it was executed.
+    /// Otherwise, the task may already be executed by another worker and its result is discarded
+    fn owns_task(&self, task: &Task) -> bool {
+        match self.queue.heartbeat_task(task) {
+            Ok(0) => {
+                warn!(
+                    "Task {} was returned to the queue while it was executed. Discarding its result",
+                    task.id
+                );
+
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Saves the attempt before the task is finalized, the history is removed together with the task
+    fn record_attempt(
+        &self,
+        task: &Task,
+        started_at: DateTime<Utc>,
+        outcome: FangTaskState,
+        result: &Result<(), FangError>,
+    ) {
+        if !self.record_attempts {
+            return;
+        }
+
+        let attempt = TaskAttempt::finished(task, &self.worker_id, started_at, outcome, result);
+
+        if let Err(error) = self.queue.record_task_attempt(&attempt) {
+            error!(
+                "Failed to record an attempt of the task {} {:?}",
+                task.id, error
+            );
+        }
+    }
+
+    /// Saves the last attempt of a task that is finalized according to the retention mode,
+    /// unless the task is removed together with its history
+    fn record_final_attempt(
+        &self,
+        task: &Task,
+        started_at: DateTime<Utc>,
+        result: &Result<(), FangError>,
+    ) {
+        if self.retention_mode.removes(result) {
+            return;
+        }
+
+        let outcome = match result {
+            Ok(_) => FangTaskState::Finished,
+            Err(_) => FangTaskState::Failed,
+        };
+
+        self.record_attempt(task, started_at, outcome, result);
+    }
+
+    fn run_with_timeout(
+        &self,
+        task: &Task,
+        context: TaskContext,
+        timeout: Duration,
+    ) -> Result<(), FangError> {
+        let (sender, receiver) = mpsc::channel();
+        let queue = self.queue.clone();
+        let metadata = task.metadata.clone();
+
+        thread::spawn(move || {
+            let result = match serde_json::from_value::<Box<dyn Runnable>>(metadata) {
+                Ok(runnable) => runnable.run(&queue, &context),
+                Err(error) => Err(FangError::undeserializable_task(error)),
+            };
+
+            sender.send(result).ok();
+        });
+
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                warn!(
+                    "Task {} timed out after {:?}. Its thread is abandoned and keeps running until the task is finished",
+                    task.id, timeout
+                );
+
+                Err(FangError::timeout(timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(FangError::retryable(
+                "The thread executing the task panicked",
+            )),
+        }
+    }
+
     pub(crate) fn run_tasks(&mut self) -> Result<(), FangError> {
         loop {
+            if self.shutdown_token.is_cancelled() {
+                return Ok(());
+            }
+
             match self.queue.fetch_and_touch_task(self.task_type.clone()) {
                 Ok(Some(task)) => {
                     let actual_task: Box<dyn Runnable> =
-                        serde_json::from_value(task.metadata.clone()).unwrap();
+                        match serde_json::from_value(task.metadata.clone()) {
+                            Ok(actual_task) => actual_task,
+                            Err(error) => {
+                                self.fail_undeserializable_task(&task, error);
+                                continue;
+                            }
+                        };
 
                     // check if task is scheduled or not
                     if let Some(CronPattern(_)) = actual_task.cron() {
@@ -89,7 +361,13 @@ where
             match self.queue.fetch_and_touch_task(self.task_type.clone()) {
                 Ok(Some(task)) => {
                     let actual_task: Box<dyn Runnable> =
-                        serde_json::from_value(task.metadata.clone()).unwrap();
+                        match serde_json::from_value(task.metadata.clone()) {
+                            Ok(actual_task) => actual_task,
+                            Err(error) => {
+                                self.fail_undeserializable_task(&task, error);
+                                continue;
+                            }
+                        };
 
                     // check if task is scheduled or not
                     if let Some(CronPattern(_)) = actual_task.cron() {
@@ -112,6 +390,18 @@ where
         }
     }
 
+    fn fail_undeserializable_task(&self, task: &Task, error: serde_json::Error) {
+        error!("Failed to deserialize the task {} {:?}", task.id, error);
+
+        let started_at = Utc::now();
+        let error = FangError::undeserializable_task(error);
+
+        self.queue.fail_task(task, &error).unwrap();
+
+        // undeserializable tasks are kept regardless of the retention mode
+        self.record_attempt(task, started_at, FangTaskState::Failed, &Err(error));
+    }
+
     pub fn maybe_reset_sleep_period(&mut self) {
         self.sleep_params.maybe_reset_sleep_period();
     }
@@ -119,7 +409,43 @@ where
     fn sleep(&mut self) {
         self.sleep_params.maybe_increase_sleep_period();
 
-        thread::sleep(self.sleep_params.sleep_period);
+        let sleep_period = self.sleep_params.sleep_period;
+
+        if self.task_listener.is_none() {
+            self.task_listener = self.queue.listen(&self.task_type).unwrap_or_else(|error| {
+                error!(
+                    "Failed to listen for tasks of type {}: {:?}",
+                    self.task_type, error
+                );
+                None
+            });
+        }
+
+        let listener = match self.task_listener.as_mut() {
+            Some(listener) => listener,
+            None => {
+                self.shutdown_token.wait_timeout(sleep_period);
+                return;
+            }
+        };
+
+        let deadline = Instant::now() + sleep_period;
+
+        loop {
+            if listener.has_notifications() {
+                return;
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero()
+                || self
+                    .shutdown_token
+                    .wait_timeout(remaining.min(NOTIFICATIONS_CHECK_INTERVAL))
+            {
+                return;
+            }
+        }
     }
 
     fn finalize_task(&self, task: Task, result: &Result<(), FangError>) {
@@ -130,7 +456,7 @@ where
                         .queue
                         .update_task_state(&task, FangTaskState::Finished)
                         .unwrap(),
-                    Err(error) => self.queue.fail_task(&task, &error.description).unwrap(),
+                    Err(error) => self.queue.fail_task(&task, error).unwrap(),
                 };
             }
 
@@ -143,25 +469,30 @@ where
                     self.queue.remove_task(task.id).unwrap();
                 }
                 Err(error) => {
-                    self.queue.fail_task(&task, &error.description).unwrap();
+                    self.queue.fail_task(&task, error).unwrap();
                 }
             },
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "blocking"))]
 mod worker_tests {
     use super::RetentionMode;
     use super::Runnable;
+    use super::ShutdownToken;
     use super::Worker;
-    use crate::fang_task_state::FangTaskState;
     use crate::queue::Queue;
     use crate::queue::Queueable;
+    use crate::task::FangTaskState;
     use crate::typetag;
     use crate::FangError;
+    use crate::SleepParams;
+    use crate::TaskContext;
     use chrono::Utc;
     use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+    use std::time::Instant;
 
     #[derive(Serialize, Deserialize)]
     struct WorkerTaskTest {
@@ -170,7 +501,7 @@ mod worker_tests {
 
     #[typetag::serde]
     impl Runnable for WorkerTaskTest {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             println!("the number is {}", self.number);
 
             Ok(())
@@ -188,12 +519,10 @@ mod worker_tests {
 
     #[typetag::serde]
     impl Runnable for FailedTask {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             let message = format!("the number is {}", self.number);
 
-            Err(FangError {
-                description: message,
-            })
+            Err(FangError::retryable(message))
         }
 
         fn max_retries(&self) -> i32 {
@@ -212,12 +541,10 @@ mod worker_tests {
 
     #[typetag::serde]
     impl Runnable for RetryTask {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             let message = format!("Saving Pepe. Attempt {}", self.number);
 
-            Err(FangError {
-                description: message,
-            })
+            Err(FangError::retryable(message))
         }
 
         fn max_retries(&self) -> i32 {
@@ -229,12 +556,36 @@ mod worker_tests {
         }
     }
 
+    #[derive(Serialize, Deserialize)]
+    struct SlowTask {}
+
+    #[typetag::serde]
+    impl Runnable for SlowTask {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
+            std::thread::sleep(Duration::from_secs(5));
+
+            Ok(())
+        }
+
+        fn max_retries(&self) -> i32 {
+            0
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            Some(Duration::from_millis(100))
+        }
+
+        fn task_type(&self) -> String {
+            "slow_task".to_string()
+        }
+    }
+
     #[derive(Serialize, Deserialize)]
     struct TaskType1 {}
 
     #[typetag::serde]
     impl Runnable for TaskType1 {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             Ok(())
         }
 
@@ -248,7 +599,7 @@ mod worker_tests {
 
     #[typetag::serde]
     impl Runnable for TaskType2 {
-        fn run(&self, _queue: &dyn Queueable) -> Result<(), FangError> {
+        fn run(&self, _queue: &dyn Queueable, _context: &TaskContext) -> Result<(), FangError> {
             Ok(())
         }
 
@@ -257,6 +608,36 @@ mod worker_tests {
         }
     }
 
+    #[test]
+    fn stops_when_shutdown_is_requested() {
+        let pool = Queue::connection_pool(1);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let shutdown_token = ShutdownToken::new();
+
+        let mut worker = Worker::<Queue>::builder()
+            .queue(queue)
+            .task_type("shutdown_test")
+            .sleep_params(SleepParams {
+                sleep_period: Duration::from_secs(15),
+                max_sleep_period: Duration::from_secs(15),
+                min_sleep_period: Duration::from_secs(15),
+                sleep_step: Duration::from_secs(0),
+            })
+            .shutdown_token(shutdown_token.clone())
+            .build();
+
+        let started_at = Instant::now();
+        let join_handle = std::thread::spawn(move || worker.run_tasks());
+
+        std::thread::sleep(Duration::from_millis(100));
+        shutdown_token.cancel();
+
+        assert!(join_handle.join().unwrap().is_ok());
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+    }
+
     // Worker tests has to commit because the worker operations commits
     #[test]
     #[ignore]
@@ -279,7 +660,13 @@ mod worker_tests {
         assert_eq!(FangTaskState::New, task.state);
 
         // this operation commits and thats why need to commit this test
-        worker.run(task.clone());
+        let started_task = Queue::update_task_state_query(
+            &mut pooled_connection,
+            &task,
+            FangTaskState::InProgress,
+        )
+        .unwrap();
+        worker.run(started_task);
 
         let found_task = Queue::find_task_by_id_query(&mut pooled_connection, task.id).unwrap();
 
@@ -347,7 +734,13 @@ mod worker_tests {
 
         assert_eq!(FangTaskState::New, task.state);
 
-        worker.run(task.clone());
+        let started_task = Queue::update_task_state_query(
+            &mut pooled_connection,
+            &task,
+            FangTaskState::InProgress,
+        )
+        .unwrap();
+        worker.run(started_task);
 
         let found_task = Queue::find_task_by_id_query(&mut pooled_connection, task.id).unwrap();
 
@@ -360,6 +753,47 @@ mod worker_tests {
         Queue::remove_tasks_of_type_query(&mut pooled_connection, "F_task").unwrap();
     }
 
+    #[test]
+    #[ignore]
+    fn fails_task_after_timeout() {
+        let task = SlowTask {};
+
+        let pool = Queue::connection_pool(5);
+
+        let queue = Queue::builder().connection_pool(pool).build();
+
+        let worker = Worker::<Queue>::builder()
+            .queue(queue)
+            .retention_mode(RetentionMode::KeepAll)
+            .task_type(task.task_type())
+            .build();
+
+        let mut pooled_connection = worker.queue.connection_pool.get().unwrap();
+
+        let task = Queue::insert_query(&mut pooled_connection, &task, Utc::now()).unwrap();
+
+        let started_at = Instant::now();
+        let started_task = Queue::update_task_state_query(
+            &mut pooled_connection,
+            &task,
+            FangTaskState::InProgress,
+        )
+        .unwrap();
+        worker.run(started_task);
+
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+
+        let found_task = Queue::find_task_by_id_query(&mut pooled_connection, task.id).unwrap();
+
+        assert_eq!(FangTaskState::Failed, found_task.state);
+        assert_eq!(
+            "The task timed out after 100ms".to_string(),
+            found_task.error_message.unwrap()
+        );
+
+        Queue::remove_tasks_of_type_query(&mut pooled_connection, "slow_task").unwrap();
+    }
+
     #[test]
     #[ignore]
     fn retries_task() {
@@ -381,7 +815,13 @@ mod worker_tests {
 
         assert_eq!(FangTaskState::New, task.state);
 
-        worker.run(task.clone());
+        let started_task = Queue::update_task_state_query(
+            &mut pooled_connection,
+            &task,
+            FangTaskState::InProgress,
+        )
+        .unwrap();
+        worker.run(started_task);
 
         std::thread::sleep(std::time::Duration::from_millis(1000));
 
@@ -1,11 +1,19 @@
-use crate::queue::Queueable;
+use crate::queueable::Queueable;
+use crate::queueable::TaskListener;
+use crate::worker::ShutdownToken;
 use crate::worker::Worker;
+use crate::Extensions;
 use crate::FangError;
 use crate::RetentionMode;
 use crate::SleepParams;
+use crate::DEFAULT_HEARTBEAT_INTERVAL;
 use log::error;
 use log::info;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
 use typed_builder::TypedBuilder;
 
 #[derive(Clone, TypedBuilder)]
@@ -29,6 +37,69 @@ where
     /// The type of tasks that will be executed by `AsyncWorkerPool`.
     #[builder(setter(into), default)]
     pub task_type: String,
+    /// restart_policy controls how workers are restarted after a panic or an error
+    #[builder(setter(into), default)]
+    pub restart_policy: RestartPolicy,
+    /// heartbeat_interval controls how often workers update the heartbeat of the task they are executing
+    #[builder(setter(into), default = DEFAULT_HEARTBEAT_INTERVAL)]
+    pub heartbeat_interval: Duration,
+    /// the shared state, for example connection pools or HTTP clients, that is passed to every task
+    /// executed by the pool in its `TaskContext`
+    #[builder(setter(into), default)]
+    pub extensions: Extensions,
+    /// record_attempts controls if workers save every execution of a task,
+    /// see `Queueable::find_task_attempts`
+    #[builder(setter(into), default)]
+    pub record_attempts: bool,
+}
+
+/// Configuration parameters for restarting workers that stopped
+/// because of a panic or an error
+#[derive(Clone, Debug, TypedBuilder)]
+pub struct RestartPolicy {
+    /// the maximum number of restarts of a worker.
+    /// After this value is reached, the worker is not restarted anymore.
+    /// `None` means that the worker is always restarted
+    #[builder(setter(into), default)]
+    pub max_restarts: Option<u64>,
+    /// the period a worker waits before it is restarted
+    #[builder(setter(into), default = Duration::from_secs(1))]
+    pub restart_backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: None,
+            restart_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A handle to the workers started by `WorkerPool::start`.
+/// It can be used to stop them.
+pub struct WorkerPoolHandle {
+    shutdown_token: ShutdownToken,
+    join_handles: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPoolHandle {
+    /// Ask all workers of the pool to stop.
+    ///
+    /// Workers stop fetching new tasks and exit after their current task is finished.
+    /// Stopped workers are not restarted.
+    pub fn shutdown(&self) {
+        self.shutdown_token.cancel();
+    }
+
+    /// Block the current thread until all workers of the pool exit
+    pub fn join(self) {
+        for join_handle in self.join_handles {
+            if let Err(error) = join_handle.join() {
+                error!("Failed to join a worker thread {:?}", error);
+            }
+        }
+    }
 }
 
 #[derive(Clone, TypedBuilder)]
@@ -39,6 +110,10 @@ where
     pub name: String,
     pub restarts: u64,
     pub worker_pool: WorkerPool<BQueue>,
+    pub shutdown_token: ShutdownToken,
+    /// the listener of notifications about new tasks that is shared by the workers of the pool
+    #[builder(default)]
+    pub task_listener: Option<TaskListener>,
 }
 
 #[derive(Clone)]
@@ -54,7 +129,21 @@ where
 {
     /// Starts the configured number of workers
     /// This is necessary in order to execute tasks.
-    pub fn start(&mut self) -> Result<(), FangError> {
+    ///
+    /// Use the returned `WorkerPoolHandle` to stop the workers.
+    pub fn start(&mut self) -> Result<WorkerPoolHandle, FangError> {
+        let shutdown_token = ShutdownToken::new();
+        let mut join_handles = Vec::new();
+
+        // all workers of the pool wait for the same task type, one connection is enough to listen on
+        let task_listener = self.queue.listen(&self.task_type).unwrap_or_else(|error| {
+            error!(
+                "Failed to listen for tasks of type {}: {:?}",
+                self.task_type, error
+            );
+            None
+        });
+
         for idx in 1..self.number_of_workers + 1 {
             let name = format!("worker_{}{}", self.task_type, idx);
 
@@ -62,11 +151,17 @@ where
                 .name(name.clone())
                 .restarts(0)
                 .worker_pool(self.clone())
+                .shutdown_token(shutdown_token.clone())
+                .task_listener(task_listener.clone())
                 .build();
 
-            worker_thread.spawn()?;
+            join_handles.push(worker_thread.spawn()?);
         }
-        Ok(())
+
+        Ok(WorkerPoolHandle {
+            shutdown_token,
+            join_handles,
+        })
     }
 }
 
@@ -74,49 +169,82 @@ impl<BQueue> WorkerThread<BQueue>
 where
     BQueue: Queueable + Clone + Sync + Send + 'static,
 {
-    fn spawn(self) -> Result<(), FangError> {
-        info!(
-            "starting a worker thread {}, number of restarts {}",
-            self.name, self.restarts
-        );
+    fn spawn(self) -> Result<JoinHandle<()>, FangError> {
+        info!("starting a worker thread {}", self.name);
 
         let builder = thread::Builder::new().name(self.name.clone());
 
-        builder
-            .spawn(move || {
-                let mut worker: Worker<BQueue> = Worker::builder()
-                    .queue(self.worker_pool.queue.clone())
-                    .task_type(self.worker_pool.task_type.clone())
-                    .retention_mode(self.worker_pool.retention_mode.clone())
-                    .sleep_params(self.worker_pool.sleep_params.clone())
-                    .build();
-
-                // Run worker
-                if let Err(error) = worker.run_tasks() {
+        let join_handle = builder
+            .spawn(move || self.supervise())
+            .map_err(FangError::from)?;
+
+        Ok(join_handle)
+    }
+
+    fn supervise(mut self) {
+        loop {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| self.run_worker()));
+
+            if self.shutdown_token.is_cancelled() {
+                info!("Worker {} stopped", self.name);
+                return;
+            }
+
+            match result {
+                Ok(Ok(())) => return,
+                Ok(Err(error)) => {
                     error!(
                         "Error executing tasks in worker '{}': {:?}",
                         self.name, error
                     );
                 }
-            })
-            .map_err(FangError::from)?;
+                Err(_) => {
+                    error!("Worker {} panicked", self.name);
+                }
+            }
 
-        Ok(())
-    }
-}
+            let restart_policy = &self.worker_pool.restart_policy;
 
-impl<BQueue> Drop for WorkerThread<BQueue>
-where
-    BQueue: Queueable + Clone + Sync + Send + 'static,
-{
-    fn drop(&mut self) {
-        self.restarts += 1;
+            if let Some(max_restarts) = restart_policy.max_restarts {
+                if self.restarts >= max_restarts {
+                    error!(
+                        "Worker {} reached the maximum number of restarts {}. It won't be restarted",
+                        self.name, max_restarts
+                    );
+                    return;
+                }
+            }
+
+            self.restarts += 1;
+
+            error!(
+                "Worker {} stopped. Restarting. The number of restarts {}",
+                self.name, self.restarts,
+            );
+
+            if self
+                .shutdown_token
+                .wait_timeout(restart_policy.restart_backoff)
+            {
+                info!("Worker {} stopped", self.name);
+                return;
+            }
+        }
+    }
 
-        error!(
-            "Worker {} stopped. Restarting. The number of restarts {}",
-            self.name, self.restarts,
-        );
+    fn run_worker(&self) -> Result<(), FangError> {
+        let mut worker: Worker<BQueue> = Worker::builder()
+            .queue(self.worker_pool.queue.clone())
+            .task_type(self.worker_pool.task_type.clone())
+            .retention_mode(self.worker_pool.retention_mode.clone())
+            .sleep_params(self.worker_pool.sleep_params.clone())
+            .shutdown_token(self.shutdown_token.clone())
+            .heartbeat_interval(self.worker_pool.heartbeat_interval)
+            .extensions(self.worker_pool.extensions.clone())
+            .record_attempts(self.worker_pool.record_attempts)
+            .task_listener(self.task_listener.clone())
+            .build();
 
-        self.clone().spawn().unwrap();
+        worker.run_tasks()
     }
 }
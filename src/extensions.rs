@@ -0,0 +1,145 @@
+//! Shared application state that is passed to tasks.
+//!
+//! Tasks are deserialized from their metadata, so they can't hold connection pools,
+//! HTTP clients or configuration. Instead, resources are added to the [`Extensions`]
+//! of a worker pool and every task gets them in its [`TaskContext`](crate::TaskContext).
+use std::any::Any;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// A map of values indexed by their type.
+///
+/// Values are shared between workers, clones of `Extensions` point to the same values.
+/// Use interior mutability, for example a `Mutex`, for values that tasks change.
+///
+/// ```
+/// use fang::Extensions;
+///
+/// struct Config {
+///     api_url: String,
+/// }
+///
+/// let extensions = Extensions::new().with(Config {
+///     api_url: "https://example.com".to_string(),
+/// });
+///
+/// assert_eq!(
+///     "https://example.com",
+///     extensions.get::<Config>().unwrap().api_url
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a value, replacing the previous value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.insert_arc(Arc::new(value));
+    }
+
+    /// Adds a value that is already shared with other parts of the application
+    pub fn insert_arc<T: Send + Sync + 'static>(&mut self, value: Arc<T>) {
+        Arc::make_mut(&mut self.map).insert(TypeId::of::<T>(), value);
+    }
+
+    /// Returns `Extensions` with the value added, see [`Extensions::insert`]
+    pub fn with<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.insert(value);
+        self
+    }
+
+    /// Returns the value of the type `T` if it was added
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+    }
+
+    /// Returns a shared pointer to the value of the type `T` if it was added.
+    /// It can be moved to other threads or tasks
+    pub fn get_arc<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|value| value.downcast::<T>().ok())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod extensions_tests {
+    use super::Extensions;
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq)]
+    struct Config {
+        name: String,
+    }
+
+    #[test]
+    fn returns_values_by_type() {
+        let extensions = Extensions::new()
+            .with(Config {
+                name: "fang".to_string(),
+            })
+            .with(42_u32);
+
+        assert_eq!("fang", extensions.get::<Config>().unwrap().name);
+        assert_eq!(Some(&42), extensions.get::<u32>());
+        assert_eq!(None, extensions.get::<u64>());
+    }
+
+    #[test]
+    fn replaces_value_of_same_type() {
+        let mut extensions = Extensions::new().with(1_u32);
+        extensions.insert(2_u32);
+
+        assert_eq!(1, extensions.len());
+        assert_eq!(Some(&2), extensions.get::<u32>());
+    }
+
+    #[test]
+    fn clones_share_values() {
+        let config = Arc::new(Config {
+            name: "fang".to_string(),
+        });
+        let mut extensions = Extensions::new();
+        extensions.insert_arc(config.clone());
+
+        let clone = extensions.clone();
+
+        assert!(Arc::ptr_eq(&config, &clone.get_arc::<Config>().unwrap()));
+    }
+
+    #[test]
+    fn inserting_into_clone_does_not_change_original() {
+        let extensions = Extensions::new().with(1_u32);
+        let clone = extensions.clone().with(2_u64);
+
+        assert_eq!(None, extensions.get::<u64>());
+        assert_eq!(Some(&2), clone.get::<u64>());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::new_tasks::NewTasks;
+use crate::task::FangTaskState;
+use crate::task::Task;
+use crate::task::TaskAttempt;
+use chrono::DateTime;
+use chrono::Duration;
+use chrono::Utc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use uuid::Uuid;
+
+/// The tasks and the attempts of the in-memory queues.
+///
+/// It implements the semantics of the `fang_tasks` and `fang_task_attempts` tables,
+/// the in-memory queues only convert runnables and errors.
+/// Clones of the store share the same tasks.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InMemoryStore {
+    tasks: Arc<Mutex<Vec<Task>>>,
+    attempts: Arc<Mutex<Vec<TaskAttempt>>>,
+}
+
+impl InMemoryStore {
+    pub(crate) fn tasks(&self) -> Vec<Task> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Task>> {
+        self.tasks.lock().unwrap()
+    }
+
+    fn is_pending(task: &Task) -> bool {
+        task.state == FangTaskState::New || task.state == FangTaskState::Retried
+    }
+
+    /// Insert the tasks of the batch, a unique task that is already pending is returned instead
+    pub(crate) fn insert(&self, new_tasks: NewTasks) -> Vec<Task> {
+        let now = Utc::now();
+        let mut tasks = self.lock();
+
+        let mut stored_tasks: Vec<Task> = tasks
+            .iter()
+            .filter(|task| {
+                Self::is_pending(task)
+                    && task.uniq_hash.is_some()
+                    && new_tasks.uniq_hashes.contains(&task.uniq_hash)
+            })
+            .cloned()
+            .collect();
+
+        for index in 0..new_tasks.ids.len() {
+            let uniq_hash = &new_tasks.uniq_hashes[index];
+
+            if uniq_hash.is_some() && stored_tasks.iter().any(|task| &task.uniq_hash == uniq_hash) {
+                continue;
+            }
+
+            let new_task = Task::builder()
+                .id(new_tasks.ids[index])
+                .metadata(new_tasks.metadatas[index].clone())
+                .error_message(None)
+                .state(FangTaskState::New)
+                .task_type(new_tasks.task_types[index].clone())
+                .uniq_hash(uniq_hash.clone())
+                .retries(0)
+                .scheduled_at(new_tasks.scheduled_ats[index])
+                .created_at(now)
+                .updated_at(now)
+                .priority(new_tasks.priorities[index])
+                .build();
+
+            tasks.push(new_task.clone());
+            stored_tasks.push(new_task);
+        }
+
+        new_tasks.into_stored_tasks(stored_tasks)
+    }
+
+    pub(crate) fn fetch_and_touch(&self, task_type: &str, limit: u32) -> Vec<Task> {
+        let now = Utc::now();
+
+        let mut tasks = self.lock();
+
+        let mut available_tasks: Vec<&mut Task> = tasks
+            .iter_mut()
+            .filter(|task| {
+                task.task_type == task_type && Self::is_pending(task) && task.scheduled_at <= now
+            })
+            .collect();
+
+        // the sort is stable, tasks created at the same time are fetched in the insertion order
+        available_tasks.sort_by(|a, b| Task::fetch_order(a, b));
+
+        available_tasks
+            .into_iter()
+            .take(limit as usize)
+            .map(|task| {
+                task.state = FangTaskState::InProgress;
+                task.heartbeat_at = Some(now);
+                task.updated_at = now;
+
+                task.clone()
+            })
+            .collect()
+    }
+
+    pub(crate) fn find(&self, id: Uuid) -> Option<Task> {
+        self.lock().iter().find(|task| task.id == id).cloned()
+    }
+
+    pub(crate) fn update(&self, id: Uuid, update: impl FnOnce(&mut Task)) -> Option<Task> {
+        let mut tasks = self.lock();
+
+        tasks.iter_mut().find(|task| task.id == id).map(|task| {
+            update(task);
+
+            task.clone()
+        })
+    }
+
+    pub(crate) fn remove(&self, predicate: impl Fn(&Task) -> bool) -> usize {
+        let mut tasks = self.lock();
+        let count = tasks.len();
+
+        tasks.retain(|task| !predicate(task));
+
+        // attempts are removed together with their tasks like in the `fang_task_attempts` table
+        self.attempts
+            .lock()
+            .unwrap()
+            .retain(|attempt| tasks.iter().any(|task| task.id == attempt.task_id));
+
+        count - tasks.len()
+    }
+
+    pub(crate) fn retry(
+        task: &mut Task,
+        retries: i32,
+        backoff_seconds: u32,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) {
+        let now = Utc::now();
+
+        task.state = FangTaskState::Retried;
+        task.error_message = Some(error_message.to_string());
+        task.error_details = error_details.cloned();
+        task.retries = retries + 1;
+        task.scheduled_at = now + Duration::seconds(backoff_seconds as i64);
+        task.updated_at = now;
+    }
+
+    /// Move `task` to the final `state` of an unsuccessful task, `Failed` or `Dead`
+    pub(crate) fn fail(
+        task: &mut Task,
+        state: FangTaskState,
+        error_message: &str,
+        error_details: Option<&serde_json::Value>,
+    ) {
+        task.state = state;
+        task.error_message = Some(error_message.to_string());
+        task.error_details = error_details.cloned();
+        task.updated_at = Utc::now();
+    }
+
+    /// Update the heartbeat of the execution of `task`, return the number of updated tasks
+    pub(crate) fn heartbeat(&self, task: &Task) -> usize {
+        let mut tasks = self.lock();
+
+        match tasks
+            .iter_mut()
+            .find(|stored_task| stored_task.id == task.id)
+        {
+            Some(stored_task)
+                if stored_task.state == FangTaskState::InProgress
+                    && stored_task.updated_at == task.updated_at =>
+            {
+                stored_task.heartbeat_at = Some(Utc::now());
+
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Retry, kill or fail the tasks in progress whose heartbeat is older than `expired_at`
+    /// and record their attempts if `record_attempts` is set.
+    ///
+    /// `backoff` returns `None` for a task that can't be deserialized, it's failed,
+    /// `Some(None)` for a task that used up its retries, it becomes dead,
+    /// and the backoff of a task that can be retried otherwise.
+    pub(crate) fn reap(
+        &self,
+        expired_at: DateTime<Utc>,
+        error_message: &str,
+        record_attempts: bool,
+        backoff: impl Fn(&Task) -> Option<Option<u32>>,
+    ) -> usize {
+        let mut reaped = 0;
+
+        for task in self.lock().iter_mut() {
+            if task.state != FangTaskState::InProgress
+                || task.heartbeat_at.unwrap_or(task.updated_at) >= expired_at
+            {
+                continue;
+            }
+
+            reaped += 1;
+
+            let reaped_task = task.clone();
+
+            let outcome = match backoff(task) {
+                Some(Some(backoff_seconds)) => {
+                    Self::retry(task, task.retries, backoff_seconds, error_message, None);
+
+                    FangTaskState::Retried
+                }
+                Some(None) => {
+                    Self::fail(task, FangTaskState::Dead, error_message, None);
+
+                    FangTaskState::Dead
+                }
+                None => {
+                    Self::fail(task, FangTaskState::Failed, error_message, None);
+
+                    FangTaskState::Failed
+                }
+            };
+
+            // the worker that executed the task can't record its attempt anymore
+            if record_attempts {
+                self.attempts
+                    .lock()
+                    .unwrap()
+                    .push(TaskAttempt::reaped(&reaped_task, outcome));
+            }
+        }
+
+        reaped
+    }
+
+    /// Reset the dead and failed tasks that match `predicate` so they are executed again
+    pub(crate) fn retry_failed(&self, predicate: impl Fn(&Task) -> bool) -> Vec<Task> {
+        let now = Utc::now();
+
+        self.lock()
+            .iter_mut()
+            .filter(|task| {
+                (task.state == FangTaskState::Failed || task.state == FangTaskState::Dead)
+                    && predicate(task)
+            })
+            .map(|task| {
+                task.state = FangTaskState::New;
+                task.retries = 0;
+                task.scheduled_at = now;
+                task.updated_at = now;
+
+                task.clone()
+            })
+            .collect()
+    }
+
+    /// Store `attempt`, return false if its task doesn't exist
+    pub(crate) fn record_attempt(&self, attempt: &TaskAttempt) -> bool {
+        let tasks = self.lock();
+
+        if !tasks.iter().any(|task| task.id == attempt.task_id) {
+            return false;
+        }
+
+        self.attempts.lock().unwrap().push(attempt.clone());
+
+        true
+    }
+
+    pub(crate) fn find_attempts(&self, task_id: Uuid) -> Vec<TaskAttempt> {
+        let mut attempts: Vec<TaskAttempt> = self
+            .attempts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|attempt| attempt.task_id == task_id)
+            .cloned()
+            .collect();
+
+        attempts.sort_by_key(|attempt| attempt.started_at);
+
+        attempts
+    }
+}
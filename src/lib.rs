@@ -35,17 +35,40 @@ pub enum CronError {
 
 /// All possible options for retaining tasks in the db after their execution.
 ///
+/// Dead tasks, the ones that used up their retries, are kept in every mode as dead letters,
+/// so they can be inspected, returned to the queue with `retry_failed_task`
+/// or removed with `discard_dead_tasks`. Tasks that fail permanently are failed in the modes that keep failed tasks
+/// and become dead letters in [`RetentionMode::RemoveAll`].
+///
 /// The default mode is [`RetentionMode::RemoveAll`]
 #[derive(Clone, Debug)]
 pub enum RetentionMode {
     /// Keep all tasks
     KeepAll,
-    /// Remove all tasks
+    /// Remove all tasks except dead ones, tasks that fail permanently become dead
     RemoveAll,
     /// Remove only successfully finished tasks
     RemoveFinished,
 }
 
+impl RetentionMode {
+    /// Returns true if a task that was executed with `result` is removed
+    #[cfg(any(feature = "blocking-core", feature = "asynk"))]
+    pub(crate) fn removes(&self, result: &Result<(), FangError>) -> bool {
+        match self {
+            RetentionMode::KeepAll => false,
+            RetentionMode::RemoveAll => true,
+            RetentionMode::RemoveFinished => result.is_ok(),
+        }
+    }
+
+    /// Returns true if tasks that failed permanently are kept as failed, otherwise they are dead letters
+    #[cfg(any(feature = "blocking-core", feature = "asynk"))]
+    pub(crate) fn keeps_failed(&self) -> bool {
+        !matches!(self, RetentionMode::RemoveAll)
+    }
+}
+
 impl Default for RetentionMode {
     fn default() -> Self {
         RetentionMode::RemoveAll
@@ -94,11 +117,188 @@ impl Default for SleepParams {
     }
 }
 
-/// An error that can happen during executing of tasks
+/// The default period between heartbeats that workers send while they are executing a task
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The error message of tasks that were returned to the queue because their heartbeat expired
+pub const EXPIRED_HEARTBEAT_ERROR: &str =
+    "The task was not finished, the worker executing it stopped sending heartbeats";
+
+/// The `worker_id` of the attempts of tasks that were returned to the queue because their heartbeat expired
+pub const REAPER_WORKER_ID: &str = "reaper";
+
+/// The maximum length of a PostgreSQL identifier, longer channel names are truncated
+#[cfg(any(feature = "blocking", feature = "asynk"))]
+const MAX_CHANNEL_NAME_LENGTH: usize = 63;
+
+/// The PostgreSQL channel that is notified about new tasks of `task_type`.
+///
+/// Notifications are not scoped to a schema, so queues in different schemas use different channels
+#[cfg(any(feature = "blocking", feature = "asynk"))]
+pub(crate) fn notification_channel(schema: Option<&str>, task_type: &str) -> String {
+    let mut channel = match schema {
+        Some(schema) => format!("{}.fang_tasks_{}", schema, task_type),
+        None => format!("fang_tasks_{}", task_type),
+    };
+
+    if channel.len() > MAX_CHANNEL_NAME_LENGTH {
+        let mut length = MAX_CHANNEL_NAME_LENGTH;
+
+        while !channel.is_char_boundary(length) {
+            length -= 1;
+        }
+
+        channel.truncate(length);
+    }
+
+    channel
+}
+
+/// The `LISTEN` statement for the `channel`
+#[cfg(any(feature = "blocking", feature = "asynk"))]
+pub(crate) fn listen_query(channel: &str) -> String {
+    format!("LISTEN {}", quote_identifier(channel))
+}
+
+/// Quotes a PostgreSQL identifier so it can be safely interpolated into a statement
+#[cfg(any(feature = "blocking", feature = "asynk"))]
+pub(crate) fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// The `search_path` of connections of a queue that stores its tasks in `schema`.
+///
+/// `public` stays in the path for the functions of extensions, like `uuid_generate_v4`.
+/// Migrations install the extensions in `public`, so they are shared by the queues of all schemas
+#[cfg(any(feature = "blocking", feature = "asynk"))]
+pub(crate) fn search_path(schema: &str) -> String {
+    format!("{},public", quote_identifier(schema))
+}
+
+/// Defines if a task that failed with a [`FangError`] is retried.
+///
+/// The default kind is [`FangErrorKind::Retryable`]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum FangErrorKind {
+    /// The task is retried after its `backoff` until it reaches `max_retries`
+    #[default]
+    Retryable,
+    /// The task fails at once, retrying it can't succeed.
+    /// For example, its input is invalid or a record it processes was deleted
+    Permanent,
+    /// The task is retried after the duration instead of its `backoff`.
+    /// It still fails after `max_retries`
+    RetryAfter(Duration),
+}
+
+/// An error that can happen during executing of tasks.
+///
+/// The `description` is saved as the `error_message` of the task and the `details`
+/// as its `error_details`, so failed tasks can be filtered and grouped by them.
 #[derive(Debug)]
 pub struct FangError {
     /// A description of an error
     pub description: String,
+    /// Defines if the task is retried
+    pub kind: FangErrorKind,
+    /// Structured information about the error
+    pub details: Option<serde_json::Value>,
+    /// The error that caused this error
+    pub source: Option<Box<dyn std::error::Error + Send + Sync + 'static>>,
+}
+
+impl FangError {
+    fn new(description: impl Into<String>, kind: FangErrorKind) -> Self {
+        FangError {
+            description: description.into(),
+            kind,
+            details: None,
+            source: None,
+        }
+    }
+
+    /// An error after which the task is retried according to its `backoff` and `max_retries`
+    pub fn retryable(description: impl Into<String>) -> Self {
+        Self::new(description, FangErrorKind::Retryable)
+    }
+
+    /// An error after which the task fails without retries
+    pub fn permanent(description: impl Into<String>) -> Self {
+        Self::new(description, FangErrorKind::Permanent)
+    }
+
+    /// An error after which the task is retried in `duration`
+    pub fn retry_after(description: impl Into<String>, duration: Duration) -> Self {
+        Self::new(description, FangErrorKind::RetryAfter(duration))
+    }
+
+    /// Attach structured details, for example `serde_json::json!({"status": 503})`
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Attach the error that caused this error
+    pub fn with_source(mut self, source: impl std::error::Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// The error of a task that was not finished during its `timeout`
+    pub fn timeout(timeout: Duration) -> Self {
+        Self::retryable(format!("The task timed out after {:?}", timeout))
+    }
+
+    /// The error of a task whose metadata can't be deserialized,
+    /// because its type is not registered anymore or its fields changed.
+    /// Such a task fails without retries
+    pub fn undeserializable_task(error: serde_json::Error) -> Self {
+        Self::permanent(format!(
+            "Failed to deserialize the task, its type is unknown or its fields changed: {}",
+            error
+        ))
+        .with_source(error)
+    }
+
+    /// The number of seconds before the next attempt of a task that failed with this error.
+    ///
+    /// `backoff` is the delay defined by the task. Returns `None` if the task must not be retried
+    #[cfg(any(feature = "blocking-core", feature = "asynk"))]
+    pub(crate) fn retry_backoff(
+        &self,
+        retries: i32,
+        max_retries: i32,
+        backoff: impl FnOnce() -> u32,
+    ) -> Option<u32> {
+        if retries >= max_retries {
+            return None;
+        }
+
+        match self.kind {
+            FangErrorKind::Retryable => Some(backoff()),
+            FangErrorKind::Permanent => None,
+            // a delay shorter than a second isn't rounded down to an immediate retry
+            FangErrorKind::RetryAfter(duration) => {
+                let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+
+                Some(u32::try_from(seconds).unwrap_or(u32::MAX))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for FangError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl std::error::Error for FangError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|source| source.as_ref() as &(dyn std::error::Error + 'static))
+    }
 }
 
 #[doc(hidden)]
@@ -126,10 +326,29 @@ pub use chrono::DateTime;
 #[doc(hidden)]
 pub use chrono::Utc;
 
-#[cfg(feature = "blocking")]
+pub mod extensions;
+pub mod task;
+
+#[cfg(any(feature = "blocking", feature = "asynk"))]
+mod migrations;
+
+#[cfg(any(feature = "blocking-core", feature = "asynk"))]
+mod in_memory_store;
+
+#[cfg(any(feature = "blocking-core", feature = "asynk"))]
+mod new_tasks;
+
+pub use task::FangTaskState;
+pub use task::Task;
+pub use task::TaskAttempt;
+pub use task::TaskContext;
+
+pub use extensions::Extensions;
+
+#[cfg(feature = "blocking-core")]
 pub mod blocking;
 
-#[cfg(feature = "blocking")]
+#[cfg(feature = "blocking-core")]
 pub use blocking::*;
 
 #[cfg(feature = "asynk")]
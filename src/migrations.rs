@@ -0,0 +1,143 @@
+//! PostgreSQL migrations embedded into the crate.
+//!
+//! [`AsyncQueue::run_migrations`](crate::asynk::AsyncQueue::run_migrations) and
+//! [`Queue::run_migrations`](crate::blocking::Queue::run_migrations) apply them, so
+//! applications don't have to copy the `migrations` directory and run the diesel CLI.
+use crate::quote_identifier;
+
+/// A versioned migration of the fang schema
+pub(crate) struct Migration {
+    /// The name of the migration directory, migrations are applied in its order
+    pub version: &'static str,
+    pub sql: &'static str,
+}
+
+/// The migrations from the `migrations` directory except the diesel initial setup
+pub(crate) const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: "2022-08-20-151615_create_fang_tasks",
+        sql: include_str!("../migrations/2022-08-20-151615_create_fang_tasks/up.sql"),
+    },
+    Migration {
+        version: "2026-10-15-090000_add_heartbeat_at_to_fang_tasks",
+        sql: include_str!("../migrations/2026-10-15-090000_add_heartbeat_at_to_fang_tasks/up.sql"),
+    },
+    Migration {
+        version: "2026-10-15-100000_add_priority_to_fang_tasks",
+        sql: include_str!("../migrations/2026-10-15-100000_add_priority_to_fang_tasks/up.sql"),
+    },
+    Migration {
+        version: "2026-10-15-130000_add_error_details_to_fang_tasks",
+        sql: include_str!("../migrations/2026-10-15-130000_add_error_details_to_fang_tasks/up.sql"),
+    },
+    Migration {
+        version: "2026-10-15-140000_create_fang_task_attempts",
+        sql: include_str!("../migrations/2026-10-15-140000_create_fang_task_attempts/up.sql"),
+    },
+    Migration {
+        version: "2026-10-15-150000_add_dead_to_fang_task_state",
+        sql: include_str!("../migrations/2026-10-15-150000_add_dead_to_fang_task_state/up.sql"),
+    },
+];
+
+/// Serializes concurrent runs of migrations, for example from several instances of an application.
+/// The lock is released when the transaction ends
+pub(crate) const LOCK_MIGRATIONS_QUERY: &str = "SELECT pg_advisory_xact_lock(3477305281692110947)";
+
+/// Installs the extensions of the migrations in `public`, which stays in the `search_path` of every queue.
+/// Otherwise `uuid-ossp` is installed in the schema of the first migrated queue and
+/// its functions are not found by queues of other schemas
+pub(crate) const CREATE_EXTENSIONS_QUERY: &str =
+    "CREATE EXTENSION IF NOT EXISTS \"uuid-ossp\" SCHEMA public";
+
+pub(crate) const CREATE_MIGRATIONS_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS fang_schema_migrations (version VARCHAR PRIMARY KEY, applied_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW())";
+
+pub(crate) const APPLIED_MIGRATIONS_QUERY: &str = "SELECT version FROM fang_schema_migrations";
+
+pub(crate) const INSERT_MIGRATION_QUERY: &str =
+    "INSERT INTO fang_schema_migrations (version) VALUES ($1)";
+
+/// The diesel CLI creates its table in the first schema of the `search_path`.
+/// A table found later in the path tracks migrations of the default schema, not of the queue's one
+pub(crate) const DIESEL_MIGRATIONS_EXIST_QUERY: &str = "SELECT to_regclass(format('%I.__diesel_schema_migrations', current_schema())) IS NOT NULL AS exists";
+
+pub(crate) const DIESEL_MIGRATIONS_QUERY: &str = "SELECT version FROM __diesel_schema_migrations";
+
+/// Creates the schema of a queue that doesn't use the default one
+pub(crate) fn create_schema_query(schema: &str) -> String {
+    format!("CREATE SCHEMA IF NOT EXISTS {}", quote_identifier(schema))
+}
+
+/// A migration that is not recorded in `fang_schema_migrations` yet
+pub(crate) struct PendingMigration {
+    pub migration: &'static Migration,
+    /// The migration was already applied with the diesel CLI,
+    /// so it only has to be recorded
+    pub applied_by_diesel: bool,
+}
+
+/// Returns migrations missing from `applied` in the order they have to be applied.
+///
+/// `diesel_applied` are versions from `__diesel_schema_migrations`
+/// for databases that were set up with the diesel CLI before.
+pub(crate) fn pending_migrations(
+    applied: &[String],
+    diesel_applied: &[String],
+) -> Vec<PendingMigration> {
+    MIGRATIONS
+        .iter()
+        .filter(|migration| !applied.iter().any(|version| version == migration.version))
+        .map(|migration| {
+            let diesel_version = diesel_version(migration.version);
+
+            PendingMigration {
+                migration,
+                applied_by_diesel: diesel_applied.contains(&diesel_version),
+            }
+        })
+        .collect()
+}
+
+/// The diesel CLI records only the digits of the timestamp of a migration,
+/// for example `20220820151615` for `2022-08-20-151615_create_fang_tasks`
+pub(crate) fn diesel_version(version: &str) -> String {
+    version
+        .split('_')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect()
+}
+
+#[cfg(test)]
+mod migrations_tests {
+    use super::diesel_version;
+    use super::pending_migrations;
+    use super::MIGRATIONS;
+
+    #[test]
+    fn diesel_version_keeps_timestamp_digits() {
+        assert_eq!(
+            "20220820151615",
+            diesel_version("2022-08-20-151615_create_fang_tasks")
+        );
+    }
+
+    #[test]
+    fn pending_migrations_skips_applied_migrations() {
+        let applied = vec![MIGRATIONS[0].version.to_string()];
+        let diesel_applied = vec![
+            "20260101000000".to_string(),
+            diesel_version(MIGRATIONS[1].version),
+        ];
+
+        let pending = pending_migrations(&applied, &diesel_applied);
+
+        assert_eq!(MIGRATIONS.len() - 1, pending.len());
+        assert_eq!(MIGRATIONS[1].version, pending[0].migration.version);
+        assert!(pending[0].applied_by_diesel);
+        assert_eq!(MIGRATIONS[2].version, pending[1].migration.version);
+        assert!(!pending[1].applied_by_diesel);
+    }
+}
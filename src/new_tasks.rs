@@ -0,0 +1,113 @@
+use crate::task::Task;
+use chrono::DateTime;
+use chrono::Utc;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The hash that identifies a unique task by its metadata
+pub(crate) fn calculate_hash(json: String) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(json.as_bytes());
+    let result = hasher.finalize();
+    hex::encode(result)
+}
+
+/// A batch of tasks that is inserted with one query, every field is a column of the batch.
+///
+/// A unique task that is repeated in the batch is added once. A unique task that is already
+/// in the queue is skipped by the query, so the queue returns the stored task instead.
+#[derive(Debug, Default)]
+pub(crate) struct NewTasks {
+    pub(crate) ids: Vec<Uuid>,
+    pub(crate) metadatas: Vec<serde_json::Value>,
+    pub(crate) task_types: Vec<String>,
+    pub(crate) uniq_hashes: Vec<Option<String>>,
+    pub(crate) scheduled_ats: Vec<DateTime<Utc>>,
+    pub(crate) priorities: Vec<i16>,
+    /// the stored task of every task of the batch, in the order they were added
+    stored_keys: Vec<StoredKey>,
+}
+
+#[derive(Debug)]
+enum StoredKey {
+    Id(Uuid),
+    UniqHash(String),
+}
+
+impl NewTasks {
+    pub(crate) fn add(
+        &mut self,
+        metadata: serde_json::Value,
+        task_type: String,
+        uniq: bool,
+        priority: i16,
+        scheduled_at: DateTime<Utc>,
+    ) {
+        let uniq_hash = uniq.then(|| calculate_hash(metadata.to_string()));
+        let id = Uuid::new_v4();
+
+        match &uniq_hash {
+            Some(uniq_hash) => {
+                let repeated = self
+                    .uniq_hashes
+                    .iter()
+                    .flatten()
+                    .any(|hash| hash == uniq_hash);
+
+                self.stored_keys
+                    .push(StoredKey::UniqHash(uniq_hash.clone()));
+
+                if repeated {
+                    return;
+                }
+            }
+            None => self.stored_keys.push(StoredKey::Id(id)),
+        }
+
+        self.ids.push(id);
+        self.metadatas.push(metadata);
+        self.task_types.push(task_type);
+        self.uniq_hashes.push(uniq_hash);
+        self.scheduled_ats.push(scheduled_at);
+        self.priorities.push(priority);
+    }
+
+    #[cfg(any(feature = "blocking", feature = "asynk"))]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The hashes of the unique tasks of the batch, sorted so concurrent batches lock them in the same order
+    #[cfg(any(feature = "blocking", feature = "asynk"))]
+    pub(crate) fn sorted_uniq_hashes(&self) -> Vec<String> {
+        let mut uniq_hashes: Vec<String> = self.uniq_hashes.iter().flatten().cloned().collect();
+        uniq_hashes.sort();
+
+        uniq_hashes
+    }
+
+    /// Match the tasks of the batch with `stored_tasks`, the inserted tasks
+    /// and the pending tasks with the hashes of the batch
+    pub(crate) fn into_stored_tasks(self, stored_tasks: Vec<Task>) -> Vec<Task> {
+        let mut tasks_by_uniq_hash: HashMap<String, Task> = HashMap::new();
+        let mut tasks_by_id: HashMap<Uuid, Task> = HashMap::new();
+
+        for task in stored_tasks {
+            if let Some(uniq_hash) = task.uniq_hash.clone() {
+                tasks_by_uniq_hash.insert(uniq_hash, task.clone());
+            }
+
+            tasks_by_id.insert(task.id, task);
+        }
+
+        self.stored_keys
+            .iter()
+            .filter_map(|key| match key {
+                StoredKey::Id(id) => tasks_by_id.get(id).cloned(),
+                StoredKey::UniqHash(uniq_hash) => tasks_by_uniq_hash.get(uniq_hash).cloned(),
+            })
+            .collect()
+    }
+}
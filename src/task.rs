@@ -0,0 +1,257 @@
+//! The task model shared by all queues.
+//!
+//! Every storage backend converts its rows to [`Task`], so code that inspects tasks
+//! works the same way with the blocking and the asynk queues.
+use crate::Extensions;
+use chrono::DateTime;
+use chrono::Utc;
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+#[cfg(feature = "blocking")]
+use crate::blocking::schema::fang_task_attempts;
+#[cfg(feature = "blocking")]
+use crate::blocking::schema::fang_tasks;
+
+/// Possible states of the task
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "blocking", derive(diesel_derive_enum::DbEnum))]
+#[cfg_attr(
+    feature = "blocking",
+    ExistingTypePath = "crate::blocking::schema::sql_types::FangTaskState"
+)]
+#[cfg_attr(
+    feature = "asynk",
+    derive(postgres_types::ToSql, postgres_types::FromSql)
+)]
+#[cfg_attr(feature = "asynk", postgres(name = "fang_task_state"))]
+pub enum FangTaskState {
+    /// The task is ready to be executed
+    #[default]
+    #[cfg_attr(feature = "asynk", postgres(name = "new"))]
+    New,
+    /// The task is being executing.
+    ///
+    /// The worker executing the task periodically updates its heartbeat.
+    /// If the worker crashes, the task stays in this state until
+    /// it's returned to the queue by `reap_expired_tasks`
+    #[cfg_attr(feature = "asynk", postgres(name = "in_progress"))]
+    InProgress,
+    /// The task failed permanently and won't be executed again.
+    ///
+    /// Failed tasks are removed or kept according to the retention mode,
+    /// tasks that can't be deserialized are always kept
+    #[cfg_attr(feature = "asynk", postgres(name = "failed"))]
+    Failed,
+    /// The task finished successfully
+    #[cfg_attr(feature = "asynk", postgres(name = "finished"))]
+    Finished,
+    /// The task is being retried. It means it failed but it's scheduled to be executed again
+    #[cfg_attr(feature = "asynk", postgres(name = "retried"))]
+    Retried,
+    /// The task used up its retries, or failed permanently with `RetentionMode::RemoveAll`,
+    /// it's a dead letter of the queue.
+    ///
+    /// Dead tasks are kept regardless of the retention mode until they are returned
+    /// to the queue with `retry_failed_task` or removed with `discard_dead_tasks`
+    #[cfg_attr(feature = "asynk", postgres(name = "dead"))]
+    Dead,
+}
+
+impl FangTaskState {
+    /// The name of the state in storages without enum types
+    #[cfg(any(feature = "sqlite", feature = "mysql", feature = "redis"))]
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            FangTaskState::New => "new",
+            FangTaskState::InProgress => "in_progress",
+            FangTaskState::Failed => "failed",
+            FangTaskState::Finished => "finished",
+            FangTaskState::Retried => "retried",
+            FangTaskState::Dead => "dead",
+        }
+    }
+
+    #[cfg(any(feature = "sqlite", feature = "mysql", feature = "redis"))]
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "new" => Some(FangTaskState::New),
+            "in_progress" => Some(FangTaskState::InProgress),
+            "failed" => Some(FangTaskState::Failed),
+            "finished" => Some(FangTaskState::Finished),
+            "retried" => Some(FangTaskState::Retried),
+            "dead" => Some(FangTaskState::Dead),
+            _ => None,
+        }
+    }
+}
+
+/// A task stored in a queue
+#[derive(Debug, Eq, PartialEq, Clone, TypedBuilder)]
+#[cfg_attr(
+    feature = "blocking",
+    derive(diesel::Queryable, diesel::QueryableByName, diesel::Identifiable)
+)]
+#[cfg_attr(feature = "blocking", diesel(table_name = fang_tasks))]
+pub struct Task {
+    #[builder(setter(into))]
+    pub id: Uuid,
+    /// The serialized task
+    #[builder(setter(into))]
+    pub metadata: serde_json::Value,
+    /// The error of the last failed execution
+    #[builder(setter(into))]
+    pub error_message: Option<String>,
+    #[builder(default, setter(into))]
+    pub state: FangTaskState,
+    #[builder(setter(into))]
+    pub task_type: String,
+    /// The hash of `metadata` if the task is unique
+    #[builder(setter(into))]
+    pub uniq_hash: Option<String>,
+    #[builder(setter(into))]
+    pub retries: i32,
+    #[builder(setter(into))]
+    pub scheduled_at: DateTime<Utc>,
+    #[builder(setter(into))]
+    pub created_at: DateTime<Utc>,
+    #[builder(setter(into))]
+    pub updated_at: DateTime<Utc>,
+    #[builder(default, setter(into))]
+    pub heartbeat_at: Option<DateTime<Utc>>,
+    #[builder(default, setter(into))]
+    pub priority: i16,
+    /// The structured details of the error of the last failed execution
+    #[builder(default, setter(into))]
+    pub error_details: Option<serde_json::Value>,
+}
+
+impl Task {
+    /// The order in which tasks are fetched: by priority, the highest first,
+    /// then by `scheduled_at` and `created_at`, the oldest first
+    #[cfg(any(feature = "blocking-core", feature = "asynk"))]
+    pub(crate) fn fetch_order(a: &Task, b: &Task) -> std::cmp::Ordering {
+        b.priority
+            .cmp(&a.priority)
+            .then(a.scheduled_at.cmp(&b.scheduled_at))
+            .then(a.created_at.cmp(&b.created_at))
+    }
+}
+
+/// One execution of a task, stored in the `fang_task_attempts` table.
+///
+/// Workers record attempts only if `record_attempts` is enabled,
+/// see `find_task_attempts` to get the history of a task
+#[derive(Debug, Eq, PartialEq, Clone, TypedBuilder)]
+#[cfg_attr(
+    feature = "blocking",
+    derive(diesel::Queryable, diesel::Insertable, diesel::Identifiable)
+)]
+#[cfg_attr(feature = "blocking", diesel(table_name = fang_task_attempts))]
+pub struct TaskAttempt {
+    #[builder(setter(into))]
+    pub id: Uuid,
+    #[builder(setter(into))]
+    pub task_id: Uuid,
+    /// The number of the attempt, it's 1 on the first execution.
+    /// It starts from 1 again after the task is returned to the queue with `retry_failed_task`
+    #[builder(setter(into))]
+    pub attempt: i32,
+    /// The id of the worker that executed the task
+    #[builder(setter(into))]
+    pub worker_id: String,
+    /// The state of the task after the attempt:
+    /// `Finished`, `Retried`, `Failed` or `Dead`
+    #[builder(setter(into))]
+    pub outcome: FangTaskState,
+    /// The error of the attempt if it failed
+    #[builder(default, setter(into))]
+    pub error_message: Option<String>,
+    #[builder(default, setter(into))]
+    pub error_details: Option<serde_json::Value>,
+    #[builder(setter(into))]
+    pub started_at: DateTime<Utc>,
+    #[builder(setter(into))]
+    pub finished_at: DateTime<Utc>,
+}
+
+impl TaskAttempt {
+    /// The attempt of `task` that started at `started_at` and just finished with `result`
+    #[cfg(any(feature = "blocking-core", feature = "asynk"))]
+    pub(crate) fn finished(
+        task: &Task,
+        worker_id: &str,
+        started_at: DateTime<Utc>,
+        outcome: FangTaskState,
+        result: &Result<(), crate::FangError>,
+    ) -> Self {
+        let error = result.as_ref().err();
+
+        TaskAttempt {
+            id: Uuid::new_v4(),
+            task_id: task.id,
+            attempt: task.retries + 1,
+            worker_id: worker_id.to_string(),
+            outcome,
+            error_message: error.map(|error| error.description.clone()),
+            error_details: error.and_then(|error| error.details.clone()),
+            started_at,
+            finished_at: Utc::now(),
+        }
+    }
+
+    /// The attempt of `task` whose worker stopped sending heartbeats,
+    /// it started when the task was fetched and `outcome` is the state it was reaped to
+    #[cfg(any(feature = "blocking-core", feature = "asynk"))]
+    pub(crate) fn reaped(task: &Task, outcome: FangTaskState) -> Self {
+        let error = crate::FangError::retryable(crate::EXPIRED_HEARTBEAT_ERROR);
+
+        Self::finished(
+            task,
+            crate::REAPER_WORKER_ID,
+            task.updated_at,
+            outcome,
+            &Err(error),
+        )
+    }
+}
+
+/// Information about the current execution of a task.
+///
+/// Workers pass it to `run`, so a task can log its id, act differently on the last attempt
+/// or continue the work of a previous attempt.
+/// It also carries the [`Extensions`] of the worker, for example connection pools or HTTP clients.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct TaskContext {
+    /// The id of the task in the queue
+    #[builder(setter(into))]
+    pub id: Uuid,
+    /// The number of previous failed attempts, it's 0 on the first execution
+    #[builder(default, setter(into))]
+    pub retries: i32,
+    /// The time the task was scheduled to be executed at
+    #[builder(setter(into))]
+    pub scheduled_at: DateTime<Utc>,
+    /// The error of the previous attempt
+    #[builder(default, setter(into))]
+    pub error_message: Option<String>,
+    /// The structured details of the error of the previous attempt
+    #[builder(default, setter(into))]
+    pub error_details: Option<serde_json::Value>,
+    /// The shared state of the worker pool executing the task
+    #[builder(default, setter(into))]
+    pub extensions: Extensions,
+}
+
+impl From<&Task> for TaskContext {
+    fn from(task: &Task) -> Self {
+        TaskContext {
+            id: task.id,
+            retries: task.retries,
+            scheduled_at: task.scheduled_at,
+            error_message: task.error_message.clone(),
+            error_details: task.error_details.clone(),
+            extensions: Extensions::default(),
+        }
+    }
+}